@@ -8,94 +8,360 @@ pub enum Prog {
 pub type Block = Vec<Stmt>;
 pub type HirBlock = Vec<HirExpr>;
 
+/// Byte range of a node in the source it was parsed from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Moves the span `offset` bytes further into the source.
+    pub fn shift(&mut self, offset: usize) {
+        self.start += offset;
+        self.end += offset;
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum HirExpr {
-    Literal(Literal, Type),
-    Binary(Box<HirExpr>, Operator, Box<HirExpr>, Type),
-    Assign(String, Box<HirExpr>, Type),
-    Var(String, Type),
-    IfElse(Box<HirExpr>, Vec<HirExpr>, Vec<HirExpr>, Type),
-    IfStatement(Box<HirExpr>, Vec<HirExpr>, Type),
-    Function(String, Vec<String>, HirBlock, Type),
-    Lambda(Vec<String>, Vec<HirExpr>, Type),
-    Return(Box<HirExpr>, Type),
-    Call(HirFunction, Type),
-    List(Vec<HirExpr>, Type),
+    Literal(Literal, Type, Span),
+    Binary(Box<HirExpr>, Operator, Box<HirExpr>, Type, Span),
+    Unary(UnaryOperator, Box<HirExpr>, Type, Span),
+    Assign(String, Box<HirExpr>, Type, Span),
+    ReAssign(String, Box<HirExpr>, Type, Span),
+    /// `xs[i][j] = v`: the variable, the indices from the outermost list
+    /// inwards, and the new value.
+    IndexAssign(String, Vec<HirExpr>, Box<HirExpr>, Type, Span),
+    Var(String, Type, Span),
+    IfElse(Box<HirExpr>, Vec<HirExpr>, Vec<HirExpr>, Type, Span),
+    IfStatement(Box<HirExpr>, Vec<HirExpr>, Type, Span),
+    While(Box<HirExpr>, HirBlock, Option<String>, Type, Span),
+    For(String, Box<HirExpr>, HirBlock, Option<String>, Type, Span),
+    /// Start, end and whether the end is included.
+    Range(Box<HirExpr>, Box<HirExpr>, bool, Type, Span),
+    Function(String, Vec<String>, HirBlock, Type, Span),
+    Lambda(Vec<String>, Vec<HirExpr>, Type, Span),
+    Return(Box<HirExpr>, Type, Span),
+    Break(Option<String>, Type, Span),
+    Continue(Option<String>, Type, Span),
+    Call(HirFunction, Type, Span),
+    List(Vec<HirExpr>, Type, Span),
+    Index(Box<HirExpr>, Box<HirExpr>, Type, Span),
+    /// A list, start, end and whether the end is included.
+    Slice(Box<HirExpr>, Box<HirExpr>, Box<HirExpr>, bool, Type, Span),
+    Interpolation(Vec<HirExpr>, Type, Span),
+    Class(String, Vec<String>, HirBlock, Type, Span),
+    New(String, Vec<HirExpr>, Type, Span),
+    Field(Box<HirExpr>, String, Type, Span),
+    MethodCall(Box<HirExpr>, HirFunction, Type, Span),
     Nothing,
 }
 
+impl HirExpr {
+    pub fn span(&self) -> Span {
+        match self {
+            HirExpr::Literal(.., span)
+            | HirExpr::Binary(.., span)
+            | HirExpr::Unary(.., span)
+            | HirExpr::Assign(.., span)
+            | HirExpr::ReAssign(.., span)
+            | HirExpr::IndexAssign(.., span)
+            | HirExpr::Var(.., span)
+            | HirExpr::IfElse(.., span)
+            | HirExpr::IfStatement(.., span)
+            | HirExpr::While(.., span)
+            | HirExpr::For(.., span)
+            | HirExpr::Range(.., span)
+            | HirExpr::Function(.., span)
+            | HirExpr::Lambda(.., span)
+            | HirExpr::Return(.., span)
+            | HirExpr::Break(.., span)
+            | HirExpr::Continue(.., span)
+            | HirExpr::Call(.., span)
+            | HirExpr::List(.., span)
+            | HirExpr::Index(.., span)
+            | HirExpr::Slice(.., span)
+            | HirExpr::Interpolation(.., span)
+            | HirExpr::Class(.., span)
+            | HirExpr::New(.., span)
+            | HirExpr::Field(.., span)
+            | HirExpr::MethodCall(.., span) => *span,
+            HirExpr::Nothing => Span::default(),
+        }
+    }
+
+    /// Type of the value the expression evaluates to.
+    pub fn ty(&self) -> Type {
+        match self {
+            HirExpr::List(_, ty, _) => Type::Primitive(Primitive::List(Box::new(ty.clone()))),
+            HirExpr::Literal(_, ty, _)
+            | HirExpr::Binary(_, _, _, ty, _)
+            | HirExpr::Unary(_, _, ty, _)
+            | HirExpr::Var(_, ty, _)
+            | HirExpr::IfElse(_, _, _, ty, _)
+            | HirExpr::IfStatement(_, _, ty, _)
+            | HirExpr::While(_, _, _, ty, _)
+            | HirExpr::For(_, _, _, _, ty, _)
+            | HirExpr::Range(_, _, _, ty, _)
+            | HirExpr::Lambda(_, _, ty, _)
+            | HirExpr::Return(_, ty, _)
+            | HirExpr::Break(_, ty, _)
+            | HirExpr::Continue(_, ty, _)
+            | HirExpr::Call(_, ty, _)
+            | HirExpr::Index(_, _, ty, _)
+            | HirExpr::Slice(_, _, _, _, ty, _)
+            | HirExpr::Interpolation(_, ty, _)
+            | HirExpr::New(_, _, ty, _)
+            | HirExpr::Field(_, _, ty, _)
+            | HirExpr::MethodCall(_, _, ty, _) => ty.clone(),
+            HirExpr::Assign(..)
+            | HirExpr::ReAssign(..)
+            | HirExpr::IndexAssign(..)
+            | HirExpr::Function(..)
+            | HirExpr::Class(..)
+            | HirExpr::Nothing => Type::Primitive(Primitive::Unit),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialOrd, PartialEq)]
 pub struct HirFunction(pub String, pub Vec<HirExpr>);
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Type {
     Primitive(Primitive),
+    Class(String),
+    Function(Vec<Type>, Box<Type>),
+    Var(usize),
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Primitive {
     Int,
+    Float,
     Bool,
     Str,
     Unit,
-    List(Box<Primitive>),
+    List(Box<Type>),
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Primitive(p) => write!(f, "{}", p),
+            Type::Class(name) => write!(f, "{}", name),
+            Type::Function(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "({}) -> {}", params.join(", "), ret)
+            }
+            Type::Var(v) => write!(f, "t{}", v),
+        }
+    }
+}
+
+impl std::fmt::Display for Primitive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Primitive::Int => write!(f, "int"),
+            Primitive::Float => write!(f, "float"),
+            Primitive::Bool => write!(f, "bool"),
+            Primitive::Str => write!(f, "string"),
+            Primitive::Unit => write!(f, "unit"),
+            Primitive::List(p) => write!(f, "[{}]", p),
+        }
+    }
 }
 
 impl From<HirExpr> for Type {
     fn from(hir: HirExpr) -> Self {
-        match hir {
-            HirExpr::List(_, Type::Primitive(Primitive::Int)) => Type::Primitive(Primitive::List(Box::new(Primitive::Int))),
-            HirExpr::List(_, Type::Primitive(Primitive::Bool)) => Type::Primitive(Primitive::List(Box::new(Primitive::Bool))),
-            HirExpr::Literal(_, Type::Primitive(Primitive::Int)) => Type::Primitive(Primitive::Int),
-            HirExpr::Literal(_, Type::Primitive(Primitive::Str)) => Type::Primitive(Primitive::Str),
-            HirExpr::Literal(_, Type::Primitive(Primitive::Bool)) => Type::Primitive(Primitive::Bool),
-            HirExpr::Binary(_, _, _, Type::Primitive(Primitive::Int)) => Type::Primitive(Primitive::Int),
-            HirExpr::Binary(_, _, _, Type::Primitive(Primitive::Bool)) => Type::Primitive(Primitive::Bool),
-            HirExpr::Function(_, _, _, Type::Primitive(Primitive::Int)) => Type::Primitive(Primitive::Int),
-            HirExpr::Call(HirFunction(_, _), Type::Primitive(Primitive::Int)) => Type::Primitive(Primitive::Int),
-            HirExpr::Call(HirFunction(_, _), Type::Primitive(Primitive::Bool)) => Type::Primitive(Primitive::Bool),
-            HirExpr::Var(_, Type::Primitive(Primitive::Int)) => Type::Primitive(Primitive::Int),
-            HirExpr::Var(_, Type::Primitive(Primitive::Bool)) => Type::Primitive(Primitive::Bool),
-            HirExpr::Return(_, Type::Primitive(Primitive::Int)) => Type::Primitive(Primitive::Int),
-            HirExpr::Return(_, Type::Primitive(Primitive::Bool)) => Type::Primitive(Primitive::Bool),
-            HirExpr::Return(_, Type::Primitive(Primitive::Str)) => Type::Primitive(Primitive::Str),
-            HirExpr::Lambda(_, _, Type::Primitive(Primitive::Int)) => Type::Primitive(Primitive::Int),
-            HirExpr::IfElse(_, _, _, Type::Primitive(Primitive::Int)) => Type::Primitive(Primitive::Int),
-            _ => unimplemented!("{:?}", hir),
-        }
+        hir.ty()
     }
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Literal {
     Bool(bool),
-    Int(i32),
+    Int(i64),
+    Float(f64),
     String(String),
 }
 
 #[derive(Clone, Debug, PartialOrd, PartialEq)]
 pub enum Stmt {
     Expr(Expr),
-    Return(Expr),
-    Assign(String, Expr, Ascription),
-    ReAssign(String, Expr),
-    IfStatement(Expr, Vec<Stmt>),
-    While(Expr, Vec<Stmt>),
-    IfElse(Expr, Vec<Stmt>, Vec<Stmt>),
-    Func(String, Vec<String>, Block, Ascription),
-    Class(String, Block),
+    Return(Expr, Span),
+    Assign(String, Expr, Option<Ascription>, Mutability, Span),
+    ReAssign(String, Expr, Span),
+    /// `target[index] = value`, where `target` is a variable or another
+    /// index into one.
+    IndexAssign(Expr, Expr, Expr, Span),
+    IfStatement(Expr, Vec<Stmt>, Span),
+    /// A loop with an optional label that `break` and `continue` can name.
+    While(Expr, Vec<Stmt>, Option<String>, Span),
+    For(String, Expr, Vec<Stmt>, Option<String>, Span),
+    Break(Option<String>, Span),
+    Continue(Option<String>, Span),
+    IfElse(Expr, Vec<Stmt>, Vec<Stmt>, Span),
+    Func(String, Vec<Param>, Block, Option<Ascription>, Span),
+    Class(String, Vec<(String, Ascription)>, Block, Span),
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expr(expr) => expr.span(),
+            Stmt::Return(.., span)
+            | Stmt::Assign(.., span)
+            | Stmt::ReAssign(.., span)
+            | Stmt::IndexAssign(.., span)
+            | Stmt::IfStatement(.., span)
+            | Stmt::While(.., span)
+            | Stmt::For(.., span)
+            | Stmt::Break(.., span)
+            | Stmt::Continue(.., span)
+            | Stmt::IfElse(.., span)
+            | Stmt::Func(.., span)
+            | Stmt::Class(.., span) => *span,
+        }
+    }
+
+    /// Moves every span in the statement `offset` bytes further, for code
+    /// parsed on its own from the middle of a source.
+    pub fn shift(&mut self, offset: usize) {
+        match self {
+            Stmt::Expr(expr) => expr.shift(offset),
+            Stmt::Return(expr, span) | Stmt::ReAssign(_, expr, span) => {
+                expr.shift(offset);
+                span.shift(offset);
+            }
+            Stmt::Assign(_, expr, _, _, span) => {
+                expr.shift(offset);
+                span.shift(offset);
+            }
+            Stmt::IndexAssign(target, index, value, span) => {
+                target.shift(offset);
+                index.shift(offset);
+                value.shift(offset);
+                span.shift(offset);
+            }
+            Stmt::IfStatement(cond, stmts, span)
+            | Stmt::While(cond, stmts, _, span)
+            | Stmt::For(_, cond, stmts, _, span) => {
+                cond.shift(offset);
+                stmts.iter_mut().for_each(|stmt| stmt.shift(offset));
+                span.shift(offset);
+            }
+            Stmt::IfElse(cond, stmts, else_stmts, span) => {
+                cond.shift(offset);
+                stmts.iter_mut().for_each(|stmt| stmt.shift(offset));
+                else_stmts.iter_mut().for_each(|stmt| stmt.shift(offset));
+                span.shift(offset);
+            }
+            Stmt::Func(_, _, stmts, _, span) | Stmt::Class(_, _, stmts, span) => {
+                stmts.iter_mut().for_each(|stmt| stmt.shift(offset));
+                span.shift(offset);
+            }
+            Stmt::Break(_, span) | Stmt::Continue(_, span) => span.shift(offset),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Expr {
-    Int(i32),
-    Bool(bool),
-    Str(String),
-    Var(String),
-    Binary(Box<Expr>, Operator, Box<Expr>),
-    Call(Call),
-    Function(Vec<String>, Vec<Stmt>),
-    List(Vec<Expr>),
+    Int(i64, Span),
+    Float(f64, Span),
+    Bool(bool, Span),
+    Str(String, Span),
+    /// `s"..."`: string pieces and embedded expressions, in order.
+    Interpolation(Vec<Expr>, Span),
+    Var(String, Span),
+    Binary(Box<Expr>, Operator, Box<Expr>, Span),
+    Unary(UnaryOperator, Box<Expr>, Span),
+    Call(Call, Span),
+    Function(Vec<Param>, Vec<Stmt>, Span),
+    List(Vec<Expr>, Span),
+    /// `xs[i]`, or a slice when the index is a range.
+    Index(Box<Expr>, Box<Expr>, Span),
+    Field(Box<Expr>, String, Span),
+    MethodCall(Box<Expr>, Function, Span),
+    Range(Box<Expr>, Box<Expr>, bool, Span),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Int(.., span)
+            | Expr::Float(.., span)
+            | Expr::Bool(.., span)
+            | Expr::Str(.., span)
+            | Expr::Interpolation(.., span)
+            | Expr::Var(.., span)
+            | Expr::Binary(.., span)
+            | Expr::Unary(.., span)
+            | Expr::Call(.., span)
+            | Expr::Function(.., span)
+            | Expr::List(.., span)
+            | Expr::Index(.., span)
+            | Expr::Field(.., span)
+            | Expr::MethodCall(.., span)
+            | Expr::Range(.., span) => *span,
+        }
+    }
+
+    /// Moves every span in the expression `offset` bytes further, for code
+    /// parsed on its own from the middle of a source.
+    pub fn shift(&mut self, offset: usize) {
+        match self {
+            Expr::Int(.., span)
+            | Expr::Float(.., span)
+            | Expr::Bool(.., span)
+            | Expr::Str(.., span)
+            | Expr::Var(.., span) => span.shift(offset),
+            Expr::Interpolation(exprs, span) | Expr::List(exprs, span) => {
+                exprs.iter_mut().for_each(|expr| expr.shift(offset));
+                span.shift(offset);
+            }
+            Expr::Call(
+                Call::Function(Function { args, .. }) | Call::Class(Class { args, .. }),
+                span,
+            ) => {
+                args.iter_mut().for_each(|expr| expr.shift(offset));
+                span.shift(offset);
+            }
+            Expr::Binary(lhs, _, rhs, span)
+            | Expr::Index(lhs, rhs, span)
+            | Expr::Range(lhs, rhs, _, span) => {
+                lhs.shift(offset);
+                rhs.shift(offset);
+                span.shift(offset);
+            }
+            Expr::Unary(_, expr, span) | Expr::Field(expr, _, span) => {
+                expr.shift(offset);
+                span.shift(offset);
+            }
+            Expr::MethodCall(expr, Function { args, .. }, span) => {
+                expr.shift(offset);
+                args.iter_mut().for_each(|expr| expr.shift(offset));
+                span.shift(offset);
+            }
+            Expr::Function(_, stmts, span) => {
+                stmts.iter_mut().for_each(|stmt| stmt.shift(offset));
+                span.shift(offset);
+            }
+        }
+    }
+}
+
+/// A function parameter, optionally annotated with its type.
+#[derive(Clone, Debug, PartialOrd, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub ascription: Option<Ascription>,
 }
 
 #[derive(Clone, Debug, PartialOrd, PartialEq)]
@@ -113,28 +379,134 @@ pub struct Function {
 #[derive(Clone, Debug, PartialOrd, PartialEq)]
 pub struct Class {
     pub identifier: String,
+    pub args: Vec<Expr>,
 }
 
 #[derive(Clone, Debug, PartialOrd, PartialEq)]
 pub enum Operator {
     Add,
     Sub,
+    Mul,
     Div,
+    Mod,
     Or,
     And,
     GreaterThan,
+    GreaterOrEqual,
     LessThan,
     LessOrEqual,
     EqTo,
+    NotEq,
     SumTo,
 }
 
+#[derive(Clone, Debug, PartialOrd, PartialEq)]
+pub enum UnaryOperator {
+    Neg,
+    Not,
+}
+
+#[derive(Clone, Debug, PartialOrd, PartialEq)]
+pub enum Mutability {
+    Immutable,
+    Mutable,
+}
+
 #[derive(Clone, Debug, PartialOrd, PartialEq)]
 pub enum Ascription {
     Int,
+    Float,
     Bool,
     Str,
+    Unit,
     List(Box<Ascription>),
+    Class(String),
+    Function(Vec<Ascription>, Box<Ascription>),
+}
+
+/// Replaces the escape sequences in the body of a string literal.
+pub fn unescape(raw: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(c @ ('\\' | '\'' | '"' | '$')) => unescaped.push(c),
+            Some(c) => return Err(format!("unknown escape sequence `\\{}`", c)),
+            None => return Err("unterminated escape sequence".into()),
+        }
+    }
+
+    Ok(unescaped)
+}
+
+/// A piece of an interpolated string, with its byte offset in the source.
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str, usize),
+    Code(&'a str, usize),
+}
+
+/// Splits the body of `s"..."`, which starts at `offset` in the source, into
+/// text and the expressions inside `${...}`.
+pub fn segments(body: &str, offset: usize) -> Result<Vec<Segment<'_>>, (String, usize)> {
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut chars = body.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '$' if matches!(chars.peek(), Some((_, '{'))) => {
+                chars.next();
+                let code_start = i + 2;
+                let mut depth = 1;
+                let code_end = loop {
+                    match chars.next() {
+                        // Braces in string literals don't count.
+                        Some((_, quote @ ('\'' | '"'))) => loop {
+                            match chars.next() {
+                                Some((_, '\\')) => {
+                                    chars.next();
+                                }
+                                Some((_, c)) if c == quote => break,
+                                Some(_) => {}
+                                None => return Err(("unterminated `${`".into(), offset + i)),
+                            }
+                        },
+                        Some((_, '{')) => depth += 1,
+                        Some((j, '}')) if depth == 1 => break j,
+                        Some((_, '}')) => depth -= 1,
+                        Some(_) => {}
+                        None => return Err(("unterminated `${`".into(), offset + i)),
+                    }
+                };
+                if text_start < i {
+                    segments.push(Segment::Text(&body[text_start..i], offset + text_start));
+                }
+                segments.push(Segment::Code(
+                    &body[code_start..code_end],
+                    offset + code_start,
+                ));
+                text_start = code_end + 1;
+            }
+            _ => {}
+        }
+    }
+    if text_start < body.len() {
+        segments.push(Segment::Text(&body[text_start..], offset + text_start));
+    }
+
+    Ok(segments)
 }
 
 #[cfg(test)]
@@ -143,15 +515,52 @@ mod tests {
 
     #[test]
     fn expression_to_type() {
-        let ty1: Type =
-            HirExpr::Literal(Literal::Bool(true), Type::Primitive(Primitive::Bool)).into();
+        let ty1: Type = HirExpr::Literal(
+            Literal::Bool(true),
+            Type::Primitive(Primitive::Bool),
+            Span::default(),
+        )
+        .into();
         let expected = Type::Primitive(Primitive::Bool);
         assert_eq!(ty1, expected);
     }
     #[test]
     fn type_is_int() {
-        let ty1: Type = HirExpr::Literal(Literal::Int(80), Type::Primitive(Primitive::Int)).into();
+        let ty1: Type = HirExpr::Literal(
+            Literal::Int(80),
+            Type::Primitive(Primitive::Int),
+            Span::default(),
+        )
+        .into();
         let expected = Type::Primitive(Primitive::Int);
         assert_eq!(ty1, expected);
     }
+
+    #[test]
+    fn interpolation_segments() {
+        let segments = segments("a ${f({x})} \\${b}${c}", 2).unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Text("a ", 2),
+                Segment::Code("f({x})", 6),
+                Segment::Text(" \\${b}", 13),
+                Segment::Code("c", 21),
+            ]
+        );
+        assert_eq!(unescape("\\'a\\tb\\n"), Ok("'a\tb\n".to_string()));
+        assert!(unescape("\\q").is_err());
+    }
+
+    #[test]
+    fn braces_in_interpolated_strings() {
+        assert_eq!(
+            segments("${len('}')} ${\"{\\\"\"}", 0).unwrap(),
+            vec![
+                Segment::Code("len('}')", 2),
+                Segment::Text(" ", 11),
+                Segment::Code("\"{\\\"\"", 14),
+            ]
+        );
+    }
 }
@@ -0,0 +1,148 @@
+use crate::ast::Span;
+use crate::error::Error;
+use crate::interpreter::Interpreter;
+use crate::value::Value;
+use std::cmp::Ordering;
+
+fn error(message: String) -> Error {
+    Error::InvalidOperation(message, Span::default())
+}
+
+// The typechecker makes sure builtins get the arguments they expect, but
+// hosts can call them with anything through `Interpreter::apply`.
+fn list(value: Value) -> Result<Vec<Value>, Error> {
+    match value {
+        Value::List(items) => Ok(items),
+        v => Err(error(format!("'{}' is not a list", v))),
+    }
+}
+
+fn bool_(value: Value) -> Result<bool, Error> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        v => Err(error(format!("'{}' is not a bool", v))),
+    }
+}
+
+/// Splits the arguments of a builtin taking a list and a function.
+fn list_and_function(vals: Vec<Value>) -> Result<(Vec<Value>, Value), Error> {
+    let mut vals = vals.into_iter();
+    match (vals.next(), vals.next()) {
+        (Some(items), Some(f)) => Ok((list(items)?, f)),
+        _ => Err(error("expected a list and a function".into())),
+    }
+}
+
+pub(crate) fn map(interpreter: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let (items, f) = list_and_function(vals)?;
+    let mut mapped = Vec::new();
+    for item in items {
+        mapped.push(interpreter.apply(f.clone(), vec![item])?);
+    }
+
+    Ok(Value::List(mapped))
+}
+
+pub(crate) fn filter(interpreter: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let (items, f) = list_and_function(vals)?;
+    let mut kept = Vec::new();
+    for item in items {
+        if bool_(interpreter.apply(f.clone(), vec![item.clone()])?)? {
+            kept.push(item);
+        }
+    }
+
+    Ok(Value::List(kept))
+}
+
+/// Combines the elements from the first to the last, starting from `init`.
+pub(crate) fn fold(interpreter: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let mut vals = vals.into_iter();
+    let (items, init, f) = match (vals.next(), vals.next(), vals.next()) {
+        (Some(items), Some(init), Some(f)) => (list(items)?, init, f),
+        _ => return Err(error("expected a list, a value and a function".into())),
+    };
+
+    items.into_iter().try_fold(init, |acc, item| {
+        interpreter.apply(f.clone(), vec![acc, item])
+    })
+}
+
+/// A stable sort. The comparison returns a negative int when its first
+/// argument goes first, a positive one when the second does, and zero when
+/// their order should be kept.
+pub(crate) fn sort_by(interpreter: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let (items, f) = list_and_function(vals)?;
+    let mut compare =
+        |a: &Value, b: &Value| match interpreter.apply(f.clone(), vec![a.clone(), b.clone()])? {
+            Value::Int(x) => Ok(x.cmp(&0)),
+            v => Err(error(format!("'{}' is not an int", v))),
+        };
+
+    // A merge sort rather than `slice::sort_by`, which can't stop at the
+    // first error and may panic if the comparison isn't a total order.
+    let mut sorted: Vec<Vec<Value>> = items.into_iter().map(|item| vec![item]).collect();
+    while sorted.len() > 1 {
+        let mut merged = Vec::new();
+        let mut runs = sorted.into_iter();
+        while let Some(left) = runs.next() {
+            let right = match runs.next() {
+                Some(right) => right,
+                None => {
+                    merged.push(left);
+                    break;
+                }
+            };
+
+            let mut run = Vec::with_capacity(left.len() + right.len());
+            let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+            while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+                if compare(a, b)? == Ordering::Greater {
+                    run.extend(right.next());
+                } else {
+                    run.extend(left.next());
+                }
+            }
+            run.extend(left);
+            run.extend(right);
+            merged.push(run);
+        }
+        sorted = merged;
+    }
+
+    Ok(Value::List(sorted.pop().unwrap_or_default()))
+}
+
+pub(crate) fn any(interpreter: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let (items, f) = list_and_function(vals)?;
+    for item in items {
+        if bool_(interpreter.apply(f.clone(), vec![item])?)? {
+            return Ok(Value::Bool(true));
+        }
+    }
+
+    Ok(Value::Bool(false))
+}
+
+pub(crate) fn all(interpreter: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let (items, f) = list_and_function(vals)?;
+    for item in items {
+        if !bool_(interpreter.apply(f.clone(), vec![item])?)? {
+            return Ok(Value::Bool(false));
+        }
+    }
+
+    Ok(Value::Bool(true))
+}
+
+/// The first element matching, in a list that is empty when none does.
+pub(crate) fn find(interpreter: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let (items, f) = list_and_function(vals)?;
+    for item in items {
+        if bool_(interpreter.apply(f.clone(), vec![item.clone()])?)? {
+            return Ok(Value::List(vec![item]));
+        }
+    }
+
+    Ok(Value::List(vec![]))
+}
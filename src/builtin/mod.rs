@@ -1,22 +1,227 @@
 #![allow(dead_code)]
+use crate::ast::{Primitive, Type};
+use crate::interpreter::Interpreter;
 use crate::value::*;
 
+mod list;
+mod string;
+pub(crate) use self::list::*;
+pub(crate) use self::string::*;
+
+/// A function every program starts with.
+pub struct Builtin {
+    pub name: &'static str,
+    /// Type variables in it are generalised by the typechecker, so every
+    /// call picks its own instantiation.
+    pub signature: Type,
+    pub function: BuiltinFn,
+}
+
+/// The builtins `Environment::default` defines and the typechecker knows
+/// the types of.
+pub fn registry() -> Vec<Builtin> {
+    let list = |t: Type| Type::Primitive(Primitive::List(Box::new(t)));
+    let function = |params: Vec<Type>, ret: Type| Type::Function(params, Box::new(ret));
+    let int = || Type::Primitive(Primitive::Int);
+    let float = || Type::Primitive(Primitive::Float);
+    let bool_ = || Type::Primitive(Primitive::Bool);
+    let string = || Type::Primitive(Primitive::Str);
+    let builtin = |name, signature, function| Builtin {
+        name,
+        signature,
+        function,
+    };
+
+    vec![
+        builtin(
+            "reduce",
+            function(vec![list(Type::Var(0))], Type::Var(0)),
+            reduce,
+        ),
+        builtin(
+            "println",
+            function(vec![Type::Var(0)], Type::Var(0)),
+            std_print,
+        ),
+        builtin("print", function(vec![Type::Var(0)], Type::Var(0)), print),
+        builtin("to_float", function(vec![int()], float()), to_float),
+        builtin("to_int", function(vec![float()], int()), to_int),
+        builtin("len", function(vec![string()], int()), len),
+        builtin(
+            "substring",
+            function(vec![string(), int(), int()], string()),
+            substring,
+        ),
+        builtin(
+            "split",
+            function(vec![string(), string()], list(string())),
+            split,
+        ),
+        builtin(
+            "join",
+            function(vec![list(string()), string()], string()),
+            join,
+        ),
+        builtin("trim", function(vec![string()], string()), trim),
+        builtin("to_upper", function(vec![string()], string()), to_upper),
+        builtin("to_lower", function(vec![string()], string()), to_lower),
+        builtin(
+            "contains",
+            function(vec![string(), string()], bool_()),
+            contains,
+        ),
+        builtin(
+            "starts_with",
+            function(vec![string(), string()], bool_()),
+            starts_with,
+        ),
+        builtin(
+            "replace",
+            function(vec![string(), string(), string()], string()),
+            replace,
+        ),
+        builtin("parse_int", function(vec![string()], int()), parse_int),
+        builtin(
+            "to_string",
+            function(vec![Type::Var(0)], string()),
+            to_string,
+        ),
+        builtin(
+            "map",
+            function(
+                vec![
+                    list(Type::Var(0)),
+                    function(vec![Type::Var(0)], Type::Var(1)),
+                ],
+                list(Type::Var(1)),
+            ),
+            map,
+        ),
+        builtin(
+            "filter",
+            function(
+                vec![list(Type::Var(0)), function(vec![Type::Var(0)], bool_())],
+                list(Type::Var(0)),
+            ),
+            filter,
+        ),
+        builtin(
+            "fold",
+            function(
+                vec![
+                    list(Type::Var(0)),
+                    Type::Var(1),
+                    function(vec![Type::Var(1), Type::Var(0)], Type::Var(1)),
+                ],
+                Type::Var(1),
+            ),
+            fold,
+        ),
+        builtin(
+            "sort_by",
+            function(
+                vec![
+                    list(Type::Var(0)),
+                    function(vec![Type::Var(0), Type::Var(0)], int()),
+                ],
+                list(Type::Var(0)),
+            ),
+            sort_by,
+        ),
+        builtin(
+            "any",
+            function(
+                vec![list(Type::Var(0)), function(vec![Type::Var(0)], bool_())],
+                bool_(),
+            ),
+            any,
+        ),
+        builtin(
+            "all",
+            function(
+                vec![list(Type::Var(0)), function(vec![Type::Var(0)], bool_())],
+                bool_(),
+            ),
+            all,
+        ),
+        builtin(
+            "find",
+            function(
+                vec![list(Type::Var(0)), function(vec![Type::Var(0)], bool_())],
+                list(Type::Var(0)),
+            ),
+            find,
+        ),
+    ]
+}
+
 pub(crate) fn std_print(
+    _: &mut Interpreter,
+    vals: Vec<crate::value::Value>,
+) -> Result<crate::value::Value, crate::error::Error> {
+    println!("{}", vals[0]);
+
+    Ok(vals[0].clone())
+}
+
+pub(crate) fn print(
+    _: &mut Interpreter,
     vals: Vec<crate::value::Value>,
 ) -> Result<crate::value::Value, crate::error::Error> {
-    println!("{:?}", &vals);
+    use std::io::Write;
+
+    print!("{}", vals[0]);
+    std::io::stdout().flush().unwrap_or_default();
 
     Ok(vals[0].clone())
 }
 
 // #![allow(dead_code)]
 pub(crate) fn reduce(
+    _: &mut Interpreter,
     elems: Vec<crate::value::Value>,
 ) -> Result<crate::value::Value, crate::error::Error> {
-    let el = match elems[0].clone() {
-        Value::List(els) => els.into_iter().reduce(|a, b| a + b),
-        _ => unreachable!(),
+    let error = |e: String| crate::error::Error::InvalidOperation(e, crate::ast::Span::default());
+    let mut els = match elems[0].clone() {
+        Value::List(els) => els.into_iter(),
+        v => return Err(error(format!("'{}' is not a list", v))),
     };
 
-    Ok(el.unwrap())
+    let first = els
+        .next()
+        .ok_or_else(|| error("cannot reduce an empty list".into()))?;
+    els.try_fold(first, |a, b| (a + b).map_err(error))
+}
+
+pub(crate) fn to_float(
+    _: &mut Interpreter,
+    vals: Vec<crate::value::Value>,
+) -> Result<crate::value::Value, crate::error::Error> {
+    match vals[0] {
+        Value::Int(x) => Ok(Value::Float(x as f64)),
+        ref v => Err(crate::error::Error::InvalidOperation(
+            format!("'{}' is not an int", v),
+            crate::ast::Span::default(),
+        )),
+    }
+}
+
+/// Truncates towards zero, failing for NaN and floats out of `int`'s range.
+pub(crate) fn to_int(
+    _: &mut Interpreter,
+    vals: Vec<crate::value::Value>,
+) -> Result<crate::value::Value, crate::error::Error> {
+    match vals[0] {
+        Value::Float(x) if x.is_finite() && x >= i64::MIN as f64 && x < i64::MAX as f64 => {
+            Ok(Value::Int(x as i64))
+        }
+        Value::Float(x) => Err(crate::error::Error::InvalidOperation(
+            format!("{:?} can't be converted to an int", x),
+            crate::ast::Span::default(),
+        )),
+        ref v => Err(crate::error::Error::InvalidOperation(
+            format!("'{}' is not a float", v),
+            crate::ast::Span::default(),
+        )),
+    }
 }
@@ -0,0 +1,113 @@
+use crate::ast::Span;
+use crate::error::Error;
+use crate::interpreter::Interpreter;
+use crate::value::Value;
+
+// The typechecker makes sure builtins get the arguments they expect, but
+// hosts can call them with anything through `Interpreter::apply`.
+fn string(value: &Value) -> Result<&str, Error> {
+    match value {
+        Value::Str(s) => Ok(s),
+        v => Err(error(format!("'{}' is not a string", v))),
+    }
+}
+
+fn int(value: &Value) -> Result<i64, Error> {
+    match value {
+        Value::Int(x) => Ok(*x),
+        v => Err(error(format!("'{}' is not an int", v))),
+    }
+}
+
+fn error(message: String) -> Error {
+    Error::InvalidOperation(message, Span::default())
+}
+
+/// Number of characters, not bytes.
+pub(crate) fn len(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    Ok(Value::Int(string(&vals[0])?.chars().count() as i64))
+}
+
+/// Characters from `start` up to, but not including, `end`.
+pub(crate) fn substring(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let s = string(&vals[0])?;
+    let (start, end) = (int(&vals[1])?, int(&vals[2])?);
+    let length = s.chars().count() as i64;
+
+    if start < 0 || end < start || end > length {
+        return Err(error(format!(
+            "substring {}..{} is out of range for a string of length {}",
+            start, end, length
+        )));
+    }
+
+    Ok(Value::Str(
+        s.chars()
+            .skip(start as usize)
+            .take((end - start) as usize)
+            .collect(),
+    ))
+}
+
+pub(crate) fn split(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let (s, separator) = (string(&vals[0])?, string(&vals[1])?);
+    if separator.is_empty() {
+        return Err(error("cannot split on an empty separator".into()));
+    }
+
+    Ok(Value::List(
+        s.split(separator)
+            .map(|part| Value::Str(part.to_string()))
+            .collect(),
+    ))
+}
+
+pub(crate) fn join(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let parts: Vec<&str> = match &vals[0] {
+        Value::List(parts) => parts.iter().map(string).collect::<Result<_, _>>()?,
+        v => return Err(error(format!("'{}' is not a list", v))),
+    };
+
+    Ok(Value::Str(parts.join(string(&vals[1])?)))
+}
+
+pub(crate) fn trim(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    Ok(Value::Str(string(&vals[0])?.trim().to_string()))
+}
+
+pub(crate) fn to_upper(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    Ok(Value::Str(string(&vals[0])?.to_uppercase()))
+}
+
+pub(crate) fn to_lower(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    Ok(Value::Str(string(&vals[0])?.to_lowercase()))
+}
+
+pub(crate) fn contains(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    Ok(Value::Bool(string(&vals[0])?.contains(string(&vals[1])?)))
+}
+
+pub(crate) fn starts_with(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    Ok(Value::Bool(
+        string(&vals[0])?.starts_with(string(&vals[1])?),
+    ))
+}
+
+pub(crate) fn replace(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    Ok(Value::Str(
+        string(&vals[0])?.replace(string(&vals[1])?, string(&vals[2])?),
+    ))
+}
+
+pub(crate) fn parse_int(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    let s = string(&vals[0])?;
+
+    s.trim()
+        .parse()
+        .map(Value::Int)
+        .map_err(|_| error(format!("'{}' is not a valid int", s)))
+}
+
+pub(crate) fn to_string(_: &mut Interpreter, vals: Vec<Value>) -> Result<Value, Error> {
+    Ok(Value::Str(vals[0].to_string()))
+}
@@ -0,0 +1,113 @@
+use crate::ast::{Prog, Type};
+use crate::environment::Environment;
+use crate::error::Error;
+use crate::interpreter::{Interpreter, Value};
+use crate::parser::ProgParser;
+use crate::type_checker::Typechecker;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Parses, typechecks and runs Martta source. What one call to `eval`
+/// defines is visible to the following ones, as in the REPL.
+///
+/// A function may fail long after the call to `eval` that defined it, so
+/// spans in errors are offsets into `source`, every source evaluated so far
+/// on its own line, rather than into the last one.
+pub struct Engine {
+    parser: ProgParser,
+    typechecker: Typechecker,
+    interpreter: Interpreter,
+    source: String,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            parser: ProgParser::new(),
+            typechecker: Typechecker::new(),
+            interpreter: Interpreter::new(Rc::new(RefCell::new(Environment::default()))),
+            source: String::new(),
+        }
+    }
+
+    /// Every source passed to `eval` so far, which the spans of errors
+    /// point into.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Parses `source` as if it was appended to the sources evaluated so far.
+    fn parse(&self, source: &str) -> Result<Prog, Error> {
+        let offset = self.source.len();
+        let Prog::Body(mut stmts) = self
+            .parser
+            .parse(source)
+            .map_err(|e| Error::from(e).shifted(offset))?;
+        stmts.iter_mut().for_each(|stmt| stmt.shift(offset));
+
+        Ok(Prog::Body(stmts))
+    }
+
+    /// Runs `source`, returning the value of its last statement. Only the
+    /// first type error is returned, `check` reports all of them. The source
+    /// is added to `source` even if it fails, for errors to be rendered.
+    ///
+    /// The program's definitions are kept only if it runs to completion. A
+    /// failed run leaves both the typechecker and the global environment as
+    /// they were, so they always agree on the type of every name.
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let ast = self.parse(source);
+        self.source.push_str(source);
+        if !self.source.ends_with('\n') {
+            self.source.push('\n');
+        }
+
+        let ast = ast?;
+        let mut typechecker = self.typechecker.clone();
+        let hir = typechecker
+            .typecheck(&ast)
+            .map_err(|mut errors| errors.remove(0))?;
+        let globals = self.interpreter.env.borrow().vals.clone();
+        match self.interpreter.run(&hir) {
+            Ok(value) => {
+                self.typechecker = typechecker;
+                Ok(value)
+            }
+            Err(e) => {
+                self.interpreter.env.borrow_mut().vals = globals;
+                Err(e)
+            }
+        }
+    }
+
+    /// Typechecks `source` as if it was passed to `eval`, without running
+    /// it or keeping its definitions. Spans point into `source` as it would
+    /// be after `eval`.
+    pub fn check(&self, source: &str) -> Result<(), Vec<Error>> {
+        let ast = self.parse(source).map_err(|e| vec![e])?;
+
+        self.typechecker.clone().typecheck(&ast).map(|_| ())
+    }
+
+    /// Defines a function implemented in Rust, which Martta code can call
+    /// as long as the arguments match `signature`, which must be a function
+    /// type.
+    pub fn register_fn<F>(&mut self, name: &str, signature: Type, function: F) -> Result<(), Error>
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, Error> + 'static,
+    {
+        self.interpreter
+            .env
+            .borrow_mut()
+            .register_fn(name, signature.clone(), function)?;
+        self.typechecker.declare(name, signature);
+
+        Ok(())
+    }
+}
@@ -1,15 +1,30 @@
+use crate::ast::{Span, Type};
 use crate::builtin::*;
-use crate::value::Value;
+use crate::error::Error;
+use crate::interpreter::Interpreter;
+use crate::value::{NativeFunction, Value};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Environment {
     pub vals: HashMap<String, Value>,
     pub enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
+// Closures capture the environment they're defined in, which usually holds
+// the closure itself, so only the names are printed.
+impl std::fmt::Debug for Environment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<&String> = self.vals.keys().collect();
+        names.sort();
+        f.debug_struct("Environment")
+            .field("vals", &names)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Default for Environment {
     fn default() -> Self {
         let mut env = Self {
@@ -17,11 +32,13 @@ impl Default for Environment {
             enclosing: None,
         };
 
-        env.define(
-            "reduce".to_string(),
-            crate::value::Value::BuiltinFunction(reduce),
-        )
-        .unwrap_or_else(|_| ());
+        for builtin in registry() {
+            env.define(
+                builtin.name.to_string(),
+                Value::BuiltinFunction(builtin.function),
+            )
+            .unwrap_or_default();
+        }
 
         env
     }
@@ -29,10 +46,37 @@ impl Default for Environment {
 
 impl Environment {
     pub fn define(&mut self, name: String, value: Value) -> Result<(), String> {
+        let value = value.stored_in(self);
         self.vals.insert(name, value);
         Ok(())
     }
 
+    /// Defines a function implemented by the embedding program. Calls to it
+    /// are typechecked against `signature` by a typechecker created with
+    /// `Typechecker::with_environment`, or told about it with
+    /// `Typechecker::declare`.
+    ///
+    /// Fails if `signature` isn't a function type.
+    pub fn register_fn<F>(&mut self, name: &str, signature: Type, function: F) -> Result<(), Error>
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, Error> + 'static,
+    {
+        if !matches!(signature, Type::Function(..)) {
+            return Err(Error::TypeError(
+                format!("`{}` must have a function type, not `{}`", name, signature),
+                Span::default(),
+            ));
+        }
+
+        let native = NativeFunction {
+            signature,
+            function: Box::new(function),
+        };
+        self.vals
+            .insert(name.to_string(), Value::NativeFunction(Rc::new(native)));
+        Ok(())
+    }
+
     pub fn with_ref(environment: Rc<RefCell<Environment>>) -> Self {
         Self {
             vals: HashMap::new(),
@@ -40,8 +84,25 @@ impl Environment {
         }
     }
 
+    /// Updates `name` in the closest environment that defines it.
+    pub fn assign(&mut self, name: String, value: Value) -> Result<(), String> {
+        if self.vals.contains_key(&name) {
+            let value = value.stored_in(self);
+            self.vals.insert(name, value);
+            return Ok(());
+        } else if let Some(enclosing) = &self.enclosing {
+            return enclosing.borrow_mut().assign(name, value);
+        }
+        Err(format!("'{}' is not defined", name))
+    }
+
+    /// The value of `name` in this environment, ignoring enclosing ones.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.vals.get(name).cloned().map(Value::loaded)
+    }
+
     pub fn get_var(&mut self, name: String) -> Option<Value> {
-        if let Some(value) = self.vals.get(&name).cloned() {
+        if let Some(value) = self.get(&name) {
             return Some(value);
         } else if let Some(enclosing) = &self.enclosing {
             return (*enclosing.borrow_mut()).get_var(name.clone());
@@ -1,17 +1,188 @@
+use crate::ast::Span;
+use lalrpop_util::{lexer::Token, ParseError};
 use thiserror::Error;
 
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum Error {
-    #[error("Invalid operation. ")]
-    ParsingError,
+    #[error("Parsing error: {0}")]
+    ParsingError(String, Span),
     #[error("Invalid operation: {0}")]
-    InvalidOperation(String),
+    InvalidOperation(String, Span),
     #[error("Types mismatch: {0}")]
-    TypeError(String),
+    TypeError(String, Span),
+    #[error("{0}")]
+    Traceback(Box<Error>, Vec<Frame>),
 }
 
-impl From<String> for Error {
-    fn from(s: String) -> Self {
-        Self::InvalidOperation(s)
+/// A Martta function call that was active when a runtime error was raised.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub call_site: Span,
+}
+
+/// The closest offset at or before `offset` that starts a character of
+/// `source`, so a span from another source can't make slicing panic.
+fn char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    offset
+}
+
+/// Line and column (both starting at 1) of a byte offset in `source`.
+fn location(source: &str, offset: usize) -> (usize, usize) {
+    let offset = char_boundary(source, offset);
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = source[..offset].matches('\n').count() + 1;
+    let column = source[line_start..offset].chars().count() + 1;
+
+    (line, column)
+}
+
+impl Error {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::ParsingError(_, span)
+            | Self::InvalidOperation(_, span)
+            | Self::TypeError(_, span) => *span,
+            Self::Traceback(e, _) => e.span(),
+        }
+    }
+
+    /// The error as raised by code parsed on its own from `offset` bytes
+    /// into the source.
+    pub fn shifted(self, offset: usize) -> Self {
+        match self {
+            Self::ParsingError(msg, mut span) => {
+                span.shift(offset);
+                Self::ParsingError(msg, span)
+            }
+            Self::InvalidOperation(msg, mut span) => {
+                span.shift(offset);
+                Self::InvalidOperation(msg, span)
+            }
+            Self::TypeError(msg, mut span) => {
+                span.shift(offset);
+                Self::TypeError(msg, span)
+            }
+            Self::Traceback(e, frames) => Self::Traceback(Box::new(e.shifted(offset)), frames),
+        }
+    }
+
+    /// Attaches the call stack to the error, unless a deeper call already did.
+    pub fn with_trace(self, frames: &[Frame]) -> Self {
+        match self {
+            Self::Traceback(..) => self,
+            e => Self::Traceback(Box::new(e), frames.to_vec()),
+        }
+    }
+
+    /// Renders the error as `file:line:col` followed by the offending source
+    /// line with the span underlined, and the call stack if there is one.
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let span = self.span();
+        let start = char_boundary(source, span.start);
+        let (line_number, column) = location(source, start);
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = &source[line_start..line_end];
+        let width = source[start..char_boundary(source, span.end).clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line_number.to_string().len());
+
+        let mut rendered = format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self,
+            gutter,
+            file,
+            line_number,
+            column,
+            gutter,
+            line_number,
+            line,
+            gutter,
+            " ".repeat(column - 1),
+            "^".repeat(width),
+        );
+
+        if let Self::Traceback(_, frames) = self {
+            rendered.push_str("\nTraceback (most recent call last):");
+            for frame in frames {
+                let (line, column) = location(source, frame.call_site.start);
+                rendered.push_str(&format!(
+                    "\n  {}:{}:{}, in call to `{}`",
+                    file, line, column, frame.function
+                ));
+            }
+        }
+
+        rendered
+    }
+}
+
+impl<'input> From<ParseError<usize, Token<'input>, Error>> for Error {
+    fn from(e: ParseError<usize, Token<'input>, Error>) -> Self {
+        match e {
+            ParseError::InvalidToken { location } => {
+                Self::ParsingError("Invalid token".into(), Span::new(location, location))
+            }
+            ParseError::UnrecognizedEOF { location, expected } => Self::ParsingError(
+                format!(
+                    "Unexpected end of input, expected one of {}",
+                    expected.join(", ")
+                ),
+                Span::new(location, location),
+            ),
+            ParseError::UnrecognizedToken {
+                token: (l, Token(_, t), r),
+                expected,
+            } => Self::ParsingError(
+                format!(
+                    "Unrecognized token `{}`, expected one of {}",
+                    t,
+                    expected.join(", ")
+                ),
+                Span::new(l, r),
+            ),
+            ParseError::ExtraToken {
+                token: (l, Token(_, t), r),
+            } => Self::ParsingError(format!("Extra token `{}`", t), Span::new(l, r)),
+            ParseError::User { error } => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_points_at_span() {
+        let source = "let a: int = 1;\nlet b: int = a + true;";
+        let error = Error::TypeError("Types do not unify".into(), Span::new(29, 37));
+        let expected = "error: Types mismatch: Types do not unify
+ --> main.mrt:2:14
+  |
+2 | let b: int = a + true;
+  |              ^^^^^^^^";
+
+        assert_eq!(error.render("main.mrt", source), expected);
+    }
+
+    #[test]
+    fn render_at_end_of_input() {
+        let source = "let a: int =";
+        let error = Error::ParsingError("Unexpected end of input".into(), Span::new(12, 12));
+
+        assert!(error
+            .render("main.mrt", source)
+            .ends_with("1 | let a: int =\n  |             ^"));
     }
 }
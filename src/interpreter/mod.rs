@@ -3,25 +3,53 @@ use crate::*;
 use std::cell::RefCell;
 use std::rc::Rc;
 pub mod value;
-pub use self::value::Value;
+pub use self::value::{BuiltinFn, Instance, NativeFunction, Scope, Value};
+
+/// Why evaluation stopped before reaching the end of a block. Only `Error`
+/// escapes the interpreter; the rest are consumed by the construct they
+/// target.
+#[derive(Debug)]
+pub enum Flow {
+    Error(Error),
+    Return(Value),
+    Break(Option<String>),
+    Continue(Option<String>),
+}
+
+impl Flow {
+    /// Whether this `break` or `continue` targets the loop labelled `label`.
+    fn targets(target: &Option<String>, label: &Option<String>) -> bool {
+        target.is_none() || target == label
+    }
+}
+
+impl From<Error> for Flow {
+    fn from(e: Error) -> Self {
+        Self::Error(e)
+    }
+}
 
 pub struct Interpreter {
     pub env: Rc<RefCell<Environment>>,
+    pub call_stack: Vec<Frame>,
 }
 
 impl Interpreter {
     pub fn new(env: Rc<RefCell<Environment>>) -> Self {
-        Self { env }
+        Self {
+            env,
+            call_stack: Vec::new(),
+        }
     }
 
     pub fn eval_block(
         &mut self,
         stmts: Vec<HirExpr>,
         env: Rc<RefCell<Environment>>,
-    ) -> Result<Value, Error> {
+    ) -> Result<Value, Flow> {
         let mut value: Value = Value::Nil;
         let previous = self.env.clone();
-        let steps = || -> Result<Value, Error> {
+        let steps = || -> Result<Value, Flow> {
             self.env = env;
             for statement in stmts {
                 value = self.expr_eval(&statement)?
@@ -34,13 +62,13 @@ impl Interpreter {
         result
     }
 
-    pub fn expr_evals(&mut self, exprs: &Vec<HirExpr>) -> Result<Vec<Value>, Error> {
+    pub fn expr_evals(&mut self, exprs: &Vec<HirExpr>) -> Result<Vec<Value>, Flow> {
         let mut vals: Vec<Value> = Vec::new();
 
         for expr in exprs {
             match self.expr_eval(expr) {
                 Ok(v) => vals.push(v),
-                Err(e) => return Err(Error::InvalidOperation(e.to_string())),
+                Err(e) => return Err(e),
             }
         }
 
@@ -51,98 +79,237 @@ impl Interpreter {
         let mut value = Value::Nil;
 
         for expr in exprs {
-            value = self.expr_eval(expr)?;
+            value = match self.expr_eval(expr) {
+                Ok(v) => v,
+                Err(Flow::Return(v)) => return Ok(v),
+                Err(Flow::Error(e)) => return Err(e),
+                Err(Flow::Break(_) | Flow::Continue(_)) => {
+                    return Err(Error::InvalidOperation(
+                        "`break` or `continue` outside of a loop".to_string(),
+                        expr.span(),
+                    ))
+                }
+            };
         }
 
         Ok(value)
     }
 
-    pub fn expr_eval(&mut self, expr: &HirExpr) -> Result<Value, Error> {
+    pub fn expr_eval(&mut self, expr: &HirExpr) -> Result<Value, Flow> {
         match expr {
-            HirExpr::Binary(lhs, op, rhs, _) => {
+            HirExpr::Binary(lhs, op, rhs, _, span) => {
                 let lhs = self.expr_eval(lhs)?;
                 let rhs = self.expr_eval(rhs)?;
 
+                let arithmetic = |result: Result<Value, String>| {
+                    result.map_err(|e| Flow::from(Error::InvalidOperation(e, *span)))
+                };
+
                 match op {
-                    Operator::Add => Ok(lhs + rhs),
-                    Operator::Sub => Ok(lhs - rhs),
-                    Operator::Div => Ok(lhs / rhs),
+                    Operator::Add => arithmetic(lhs + rhs),
+                    Operator::Sub => arithmetic(lhs - rhs),
+                    Operator::Mul => arithmetic(lhs * rhs),
+                    Operator::Div => arithmetic(lhs / rhs),
+                    Operator::Mod => arithmetic(lhs % rhs),
                     Operator::GreaterThan => Ok(Value::Bool(lhs > rhs)),
+                    Operator::GreaterOrEqual => Ok(Value::Bool(lhs >= rhs)),
                     Operator::LessThan => Ok(Value::Bool(lhs < rhs)),
                     Operator::LessOrEqual => Ok(Value::Bool(lhs <= rhs)),
                     Operator::EqTo => Ok(Value::Bool(lhs == rhs)),
+                    Operator::NotEq => Ok(Value::Bool(lhs != rhs)),
                     Operator::Or => match (rhs, lhs) {
                         (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a || b)),
                         (_, _) => Err(Error::InvalidOperation(
                             "Only boolean types allowed in Or operations".to_string(),
-                        )),
+                            *span,
+                        )
+                        .into()),
                     },
                     Operator::And => match (rhs, lhs) {
                         (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a && b)),
                         (_, _) => Err(Error::InvalidOperation(
-                            "Only boolean types allowed in Or operations".to_string(),
-                        )),
+                            "Only boolean types allowed in And operations".to_string(),
+                            *span,
+                        )
+                        .into()),
                     },
-                    _ => unreachable!(),
+                    // The typechecker turns `x += y` into an assignment.
+                    Operator::SumTo => Err(Error::InvalidOperation(
+                        "`+=` can only be used as a statement".to_string(),
+                        *span,
+                    )
+                    .into()),
                 }
             }
-            HirExpr::Literal(Literal::Int(l), _) => Ok(Value::Int(*l)),
-            HirExpr::Literal(Literal::Bool(b), _) => Ok(Value::Bool(*b)),
-            HirExpr::Literal(Literal::String(s), _) => Ok(Value::Str(s.to_string())),
-            HirExpr::Function(name, args, stmts, _) => {
-                let v = Value::Function(args.to_vec(), stmts.to_vec());
+            HirExpr::Unary(op, operand, _, span) => match (op, self.expr_eval(operand)?) {
+                (UnaryOperator::Neg, v @ (Value::Int(_) | Value::Float(_))) => {
+                    Ok((-v).map_err(|e| Error::InvalidOperation(e, *span))?)
+                }
+                (UnaryOperator::Not, v @ Value::Bool(_)) => Ok(!v),
+                (_, v) => Err(Error::InvalidOperation(
+                    format!("Invalid operand '{}' for unary operator", v),
+                    *span,
+                )
+                .into()),
+            },
+            HirExpr::Literal(Literal::Int(l), ..) => Ok(Value::Int(*l)),
+            HirExpr::Literal(Literal::Float(f), ..) => Ok(Value::Float(*f)),
+            HirExpr::Literal(Literal::Bool(b), ..) => Ok(Value::Bool(*b)),
+            HirExpr::Literal(Literal::String(s), ..) => Ok(Value::Str(s.to_string())),
+            HirExpr::Function(name, args, stmts, _, span) => {
+                let v = Value::Function(
+                    args.to_vec(),
+                    stmts.to_vec(),
+                    Scope::Strong(self.env.clone()),
+                );
 
                 match self.env.borrow_mut().define(name.clone(), v) {
                     Ok(_) => Ok(Value::Nil),
-                    Err(e) => Err(Error::InvalidOperation(e)),
+                    Err(e) => Err(Error::InvalidOperation(e, *span).into()),
                 }
             }
-            HirExpr::IfStatement(cond, stmts, _) => match self.expr_eval(cond) {
-                Ok(b) => match b {
-                    Value::Bool(true) => self.eval_block(stmts.to_vec(), self.env.clone()),
-                    Value::Bool(false) => Ok(Value::Nil),
-                    _ => unreachable!(),
-                },
-                Err(_) => Err(Error::InvalidOperation(
-                    "Expression must be boolean".to_string(),
-                )),
+            HirExpr::IfStatement(cond, stmts, _, span) => match self.expr_eval(cond)? {
+                Value::Bool(true) => self.eval_block(stmts.to_vec(), self.child_env()),
+                Value::Bool(false) => Ok(Value::Nil),
+                _ => Err(
+                    Error::InvalidOperation("Expression must be boolean".to_string(), *span).into(),
+                ),
             },
-            HirExpr::IfElse(cond, stmts, estmt, _) => match self.expr_eval(cond) {
-                Ok(b) => match b {
-                    Value::Bool(true) => self.eval_block(stmts.to_vec(), self.env.clone()),
-                    Value::Bool(false) => self.eval_block(estmt.to_vec(), self.env.clone()),
-                    _ => unreachable!(),
-                },
-                Err(_) => Err(Error::InvalidOperation(
-                    "Expression must be boolean".to_string(),
-                )),
+            HirExpr::IfElse(cond, stmts, estmt, _, span) => match self.expr_eval(cond)? {
+                Value::Bool(true) => self.eval_block(stmts.to_vec(), self.child_env()),
+                Value::Bool(false) => self.eval_block(estmt.to_vec(), self.child_env()),
+                _ => Err(
+                    Error::InvalidOperation("Expression must be boolean".to_string(), *span).into(),
+                ),
             },
-            HirExpr::Return(e, _) => {
-                let value = match self.expr_eval(e) {
-                    Ok(v) => v,
-                    Err(e) => return Err(e),
+            HirExpr::While(cond, stmts, label, _, span) => {
+                loop {
+                    match self.expr_eval(cond)? {
+                        Value::Bool(true) => {
+                            let environment = self.child_env();
+                            if !self.iterate(stmts, environment, label)? {
+                                break;
+                            }
+                        }
+                        Value::Bool(false) => break,
+                        _ => {
+                            return Err(Error::InvalidOperation(
+                                "Expression must be boolean".to_string(),
+                                *span,
+                            )
+                            .into())
+                        }
+                    }
+                }
+
+                Ok(Value::Nil)
+            }
+            HirExpr::For(var, iter, stmts, label, _, span) => {
+                // Ranges are iterated without building the list.
+                let items: Box<dyn Iterator<Item = Value>> = match &**iter {
+                    HirExpr::Range(start, end, inclusive, _, span) => {
+                        self.range(start, end, *inclusive, *span)?
+                    }
+                    _ => match self.expr_eval(iter)? {
+                        Value::List(items) => Box::new(items.into_iter()),
+                        v => {
+                            return Err(Error::InvalidOperation(
+                                format!("'{}' is not a list", v),
+                                *span,
+                            )
+                            .into())
+                        }
+                    },
                 };
-                Ok(value)
+
+                for item in items {
+                    let environment = self.child_env();
+                    environment
+                        .borrow_mut()
+                        .define(var.clone(), item)
+                        .map_err(|e| Error::InvalidOperation(e, *span))?;
+                    if !self.iterate(stmts, environment, label)? {
+                        break;
+                    }
+                }
+
+                Ok(Value::Nil)
+            }
+            HirExpr::Range(start, end, inclusive, _, span) => Ok(Value::List(
+                self.range(start, end, *inclusive, *span)?.collect(),
+            )),
+            HirExpr::Return(e, ..) => {
+                let value = self.expr_eval(e)?;
+                Err(Flow::Return(value))
             }
-            HirExpr::Assign(name, rhs, _) => match self.expr_eval(rhs) {
+            HirExpr::Break(label, ..) => Err(Flow::Break(label.clone())),
+            HirExpr::Continue(label, ..) => Err(Flow::Continue(label.clone())),
+            HirExpr::Assign(name, rhs, _, span) => match self.expr_eval(rhs) {
                 Ok(v) => {
-                    self.env.borrow_mut().define(name.to_string(), v)?;
+                    self.env
+                        .borrow_mut()
+                        .define(name.to_string(), v)
+                        .map_err(|e| Error::InvalidOperation(e, *span))?;
                     Ok(Value::Nil)
                 }
                 Err(e) => Err(e),
             },
-            HirExpr::Var(name, _) => match self.env.borrow_mut().get_var(name.to_string()) {
+            HirExpr::ReAssign(name, rhs, _, span) => {
+                let v = self.expr_eval(rhs)?;
+                self.env
+                    .borrow_mut()
+                    .assign(name.to_string(), v)
+                    .map_err(|e| Error::InvalidOperation(e, *span))?;
+                Ok(Value::Nil)
+            }
+            HirExpr::IndexAssign(name, indices, rhs, _, span) => {
+                let error = |e| Flow::from(Error::InvalidOperation(e, *span));
+                let mut positions = Vec::new();
+                for index in indices {
+                    positions.push(self.position(index)?);
+                }
+                let value = self.expr_eval(rhs)?;
+
+                let mut list = self
+                    .env
+                    .borrow_mut()
+                    .get_var(name.to_string())
+                    .ok_or_else(|| error(format!("'{}' is not defined", name)))?;
+                let mut slot = &mut list;
+                for position in positions {
+                    slot = slot.element(position).map_err(error)?;
+                }
+                *slot = value;
+
+                self.env
+                    .borrow_mut()
+                    .assign(name.to_string(), list)
+                    .map_err(error)?;
+                Ok(Value::Nil)
+            }
+            HirExpr::Var(name, _, span) => match self.env.borrow_mut().get_var(name.to_string()) {
                 Some(v) => Ok(v),
-                None => Err(Error::InvalidOperation(format!(
-                    "'{}' is not defined",
-                    name
-                ))),
+                None => {
+                    Err(Error::InvalidOperation(format!("'{}' is not defined", name), *span).into())
+                }
             },
-            HirExpr::Lambda(args, stmts, _) => {
-                let f = Value::Function(args.to_vec(), stmts.to_vec());
+            HirExpr::Lambda(args, stmts, ..) => {
+                let f = Value::Function(
+                    args.to_vec(),
+                    stmts.to_vec(),
+                    Scope::Strong(self.env.clone()),
+                );
                 Ok(f)
             }
-            HirExpr::List(elems, _) => {
+            HirExpr::Interpolation(parts, ..) => {
+                let mut string = String::new();
+                for part in parts {
+                    string.push_str(&self.expr_eval(part)?.to_string());
+                }
+
+                Ok(Value::Str(string))
+            }
+            HirExpr::List(elems, ..) => {
                 let mut vals = Vec::new();
 
                 for el in elems {
@@ -156,57 +323,298 @@ impl Interpreter {
 
                 Ok(list)
             }
-            HirExpr::Call(HirFunction(function, args), _) => {
-                let mut vals = Vec::new();
+            HirExpr::Index(list, index, _, span) => {
+                let mut list = self.expr_eval(list)?;
+                let position = self.position(index)?;
 
-                for arg in args {
-                    match self.expr_eval(arg) {
-                        Ok(v) => vals.push(v),
-                        Err(e) => return Err(e),
-                    }
+                match list.element(position) {
+                    Ok(element) => Ok(element.clone()),
+                    Err(e) => Err(Error::InvalidOperation(e, *span).into()),
                 }
+            }
+            HirExpr::Slice(list, start, end, inclusive, _, span) => {
+                let list = self.expr_eval(list)?;
+                let (start, end) = (self.position(start)?, self.position(end)?);
+
+                Ok(list
+                    .slice(start, end, *inclusive)
+                    .map_err(|e| Error::InvalidOperation(e, *span))?)
+            }
+            HirExpr::Call(HirFunction(function, args), _, span) => {
+                let vals = self.expr_evals(args)?;
 
                 let function_defined = match self.env.borrow_mut().get_var(function.to_string()) {
                     Some(v) => v,
                     None => {
-                        return Err(Error::InvalidOperation(format!(
-                            "Function '{}' is not defined",
-                            &function
-                        )))
+                        return Err(Error::InvalidOperation(
+                            format!("Function '{}' is not defined", &function),
+                            *span,
+                        )
+                        .into())
                     }
                 };
 
-                match function_defined {
-                    Value::BuiltinFunction(f) => f(vals),
-                    Value::Function(params, stmts) => {
-                        let arity = params.len() == args.len();
-
-                        match arity {
-                            true => {
-                                let environment =
-                                    Rc::new(RefCell::new(Environment::with_ref(self.env.clone())));
-                                for (param, argument) in params.iter().zip(vals.iter()) {
-                                    environment
-                                        .borrow_mut()
-                                        .define(param.clone(), argument.clone())?;
-                                }
-                                self.eval_block(stmts, environment)
-                            }
-                            _ => Err(Error::InvalidOperation(format!(
-                                "function '{}' accepts {} parameters but {} were provided.",
-                                function,
-                                params.len(),
-                                args.len()
-                            ))),
+                Ok(self.call(function, function_defined, vals, *span)?)
+            }
+            HirExpr::Class(name, params, stmts, _, span) => {
+                let class = Value::Class(
+                    name.clone(),
+                    params.to_vec(),
+                    stmts.to_vec(),
+                    Scope::Strong(self.env.clone()),
+                );
+
+                self.env
+                    .borrow_mut()
+                    .define(name.clone(), class)
+                    .map_err(|e| Error::InvalidOperation(e, *span))?;
+                Ok(Value::Nil)
+            }
+            HirExpr::New(name, args, _, span) => {
+                let vals = self.expr_evals(args)?;
+
+                let class = self.env.borrow_mut().get_var(name.to_string());
+
+                match class {
+                    Some(Value::Class(_, params, stmts, scope)) => match scope.upgrade() {
+                        Some(enclosing) => {
+                            self.instantiate(name, params, stmts, vals, enclosing, *span)
                         }
+                        None => Err(Error::InvalidOperation(
+                            format!("the scope of class '{}' no longer exists", name),
+                            *span,
+                        )
+                        .into()),
+                    },
+                    _ => Err(Error::InvalidOperation(
+                        format!("Class '{}' is not defined", name),
+                        *span,
+                    )
+                    .into()),
+                }
+            }
+            HirExpr::Field(obj, field, _, span) => match self.expr_eval(obj)? {
+                Value::Instance(instance) => match instance.env.borrow().get(field) {
+                    Some(v) => Ok(v),
+                    None => Err(Error::InvalidOperation(
+                        format!("'{}' has no field '{}'", instance.class, field),
+                        *span,
+                    )
+                    .into()),
+                },
+                v => Err(
+                    Error::InvalidOperation(format!("'{}' is not an instance", v), *span).into(),
+                ),
+            },
+            HirExpr::MethodCall(obj, HirFunction(method, args), _, span) => {
+                let instance = match self.expr_eval(obj)? {
+                    Value::Instance(instance) => instance,
+                    v => {
+                        return Err(Error::InvalidOperation(
+                            format!("'{}' is not an instance", v),
+                            *span,
+                        )
+                        .into())
+                    }
+                };
+                let vals = self.expr_evals(args)?;
+                let function = instance.env.borrow().get(method);
+
+                match function {
+                    Some(f) => {
+                        Ok(self.call(&format!("{}.{}", instance.class, method), f, vals, *span)?)
                     }
-                    _ => Err(Error::InvalidOperation(format!(
-                        "'{}' isn't a function",
-                        function
-                    ))),
+                    None => Err(Error::InvalidOperation(
+                        format!("'{}' has no method '{}'", instance.class, method),
+                        *span,
+                    )
+                    .into()),
+                }
+            }
+            HirExpr::Nothing => Ok(Value::Nil),
+        }
+    }
+
+    /// The ints from `start` to `end`, in order.
+    fn range(
+        &mut self,
+        start: &HirExpr,
+        end: &HirExpr,
+        inclusive: bool,
+        span: Span,
+    ) -> Result<Box<dyn Iterator<Item = Value>>, Flow> {
+        match (self.expr_eval(start)?, self.expr_eval(end)?) {
+            (Value::Int(start), Value::Int(end)) if inclusive => {
+                Ok(Box::new((start..=end).map(Value::Int)))
+            }
+            (Value::Int(start), Value::Int(end)) => Ok(Box::new((start..end).map(Value::Int))),
+            _ => Err(
+                Error::InvalidOperation("Only integers allowed in ranges".to_string(), span).into(),
+            ),
+        }
+    }
+
+    /// A fresh environment for a block, enclosed by the current one.
+    fn child_env(&self) -> Rc<RefCell<Environment>> {
+        Rc::new(RefCell::new(Environment::with_ref(self.env.clone())))
+    }
+
+    /// Evaluates a list index or slice bound.
+    fn position(&mut self, expr: &HirExpr) -> Result<i64, Flow> {
+        match self.expr_eval(expr)? {
+            Value::Int(i) => Ok(i),
+            v => Err(Error::InvalidOperation(format!("'{}' is not an int", v), expr.span()).into()),
+        }
+    }
+
+    /// Runs one iteration of a loop labelled `label`, returning whether the
+    /// loop should keep going.
+    fn iterate(
+        &mut self,
+        stmts: &[HirExpr],
+        environment: Rc<RefCell<Environment>>,
+        label: &Option<String>,
+    ) -> Result<bool, Flow> {
+        match self.eval_block(stmts.to_vec(), environment) {
+            Err(Flow::Break(target)) if Flow::targets(&target, label) => Ok(false),
+            Err(Flow::Continue(target)) if Flow::targets(&target, label) => Ok(true),
+            result => result.map(|_| true),
+        }
+    }
+
+    /// Calls `function` with its parameters bound in a child of the
+    /// environment it captured.
+    pub fn call(
+        &mut self,
+        name: &str,
+        function: Value,
+        vals: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, Error> {
+        if !matches!(
+            function,
+            Value::BuiltinFunction(_) | Value::NativeFunction(_) | Value::Function(..)
+        ) {
+            return Err(Error::InvalidOperation(
+                format!("'{}' isn't a function", name),
+                span,
+            ));
+        }
+
+        self.call_stack.push(Frame {
+            function: name.to_string(),
+            call_site: span,
+        });
+        let result = self.apply(function, vals);
+        self.call_stack.pop();
+
+        result
+    }
+
+    /// Calls `function` on behalf of the innermost frame of the call stack.
+    /// This is how builtins call the Martta functions they are given.
+    pub fn apply(&mut self, function: Value, vals: Vec<Value>) -> Result<Value, Error> {
+        let Frame {
+            function: name,
+            call_site: span,
+        } = self.call_stack.last().cloned().unwrap_or(Frame {
+            function: String::new(),
+            call_site: Span::default(),
+        });
+
+        match function {
+            // Builtins don't know where they were called from.
+            Value::BuiltinFunction(f) => f(self, vals).map_err(|e| match e {
+                Error::InvalidOperation(msg, _) => Error::InvalidOperation(msg, span),
+                e => e,
+            }),
+            Value::NativeFunction(native) => {
+                let value = (native.function)(self, vals).map_err(|e| match e {
+                    Error::InvalidOperation(msg, _) => Error::InvalidOperation(msg, span),
+                    e => e,
+                })?;
+
+                // The typechecker trusted the signature, so a value that
+                // doesn't match it must not reach the program.
+                match &native.signature {
+                    Type::Function(_, ret) if !value.has_type(ret) => Err(Error::InvalidOperation(
+                        format!(
+                            "native function '{}' returned '{}', expected a value of type {}",
+                            name, value, ret
+                        ),
+                        span,
+                    )),
+                    _ => Ok(value),
+                }
+            }
+            Value::Function(params, stmts, scope) => {
+                if params.len() != vals.len() {
+                    return Err(Error::InvalidOperation(
+                        format!(
+                            "function '{}' accepts {} parameters but {} were provided.",
+                            name,
+                            params.len(),
+                            vals.len()
+                        ),
+                        span,
+                    ));
+                }
+
+                let enclosing = scope.upgrade().ok_or_else(|| {
+                    Error::InvalidOperation(
+                        format!("the scope of function '{}' no longer exists", name),
+                        span,
+                    )
+                })?;
+                let environment = Rc::new(RefCell::new(Environment::with_ref(enclosing)));
+                for (param, argument) in params.iter().zip(vals) {
+                    environment
+                        .borrow_mut()
+                        .define(param.clone(), argument)
+                        .map_err(|e| Error::InvalidOperation(e, span))?;
+                }
+
+                match self.eval_block(stmts, environment) {
+                    Ok(v) | Err(Flow::Return(v)) => Ok(v),
+                    Err(Flow::Error(e)) => Err(e.with_trace(&self.call_stack)),
+                    Err(Flow::Break(_) | Flow::Continue(_)) => Err(Error::InvalidOperation(
+                        "`break` or `continue` outside of a loop".to_string(),
+                        span,
+                    )
+                    .with_trace(&self.call_stack)),
                 }
             }
-            _ => unimplemented!(),
+            _ => Err(Error::InvalidOperation(
+                format!("'{}' isn't a function", name),
+                span,
+            )),
         }
     }
+
+    /// Runs a class body in a fresh environment holding the constructor
+    /// arguments, enclosed by the one the class was declared in. Whatever
+    /// the body defines becomes the instance's fields and methods.
+    fn instantiate(
+        &mut self,
+        class: &str,
+        params: Vec<String>,
+        stmts: Vec<HirExpr>,
+        vals: Vec<Value>,
+        enclosing: Rc<RefCell<Environment>>,
+        span: Span,
+    ) -> Result<Value, Flow> {
+        let environment = Rc::new(RefCell::new(Environment::with_ref(enclosing)));
+        for (param, argument) in params.into_iter().zip(vals) {
+            environment
+                .borrow_mut()
+                .define(param, argument)
+                .map_err(|e| Error::InvalidOperation(e, span))?;
+        }
+        self.eval_block(stmts, environment.clone())?;
+
+        Ok(Value::Instance(Rc::new(Instance {
+            class: class.to_string(),
+            env: environment,
+        })))
+    }
 }
@@ -1,76 +1,355 @@
 use crate::ast::*;
+use crate::environment::Environment;
 use crate::error::Error;
+use crate::interpreter::Interpreter;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::{Rc, Weak};
 
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
+/// A native function. It gets the interpreter so it can call back into
+/// Martta functions passed to it, through `Interpreter::apply`.
+pub type BuiltinFn = fn(&mut Interpreter, Vec<Value>) -> Result<Value, Error>;
+pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value, Error>;
+
+/// A function defined by the program embedding Martta, registered with
+/// `Environment::register_fn`. Unlike a builtin it can capture host state.
+pub struct NativeFunction {
+    /// Declared by the host, the typechecker checks calls against it.
+    pub signature: Type,
+    pub function: Box<NativeFn>,
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("signature", &self.signature)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Value {
-    Int(i32),
+    Int(i64),
+    Float(f64),
     Bool(bool),
     Str(String),
     List(Vec<Value>),
-    BuiltinFunction(fn(Vec<Value>) -> Result<Value, Error>),
-    Function(Vec<String>, Vec<HirExpr>),
+    BuiltinFunction(BuiltinFn),
+    NativeFunction(Rc<NativeFunction>),
+    /// Parameters, body and the environment the function was created in.
+    Function(Vec<String>, Vec<HirExpr>, Scope),
+    Class(String, Vec<String>, Vec<HirExpr>, Scope),
+    Instance(Rc<Instance>),
     Nil,
 }
 
+/// The environment a function or class was created in. While one is stored
+/// in that same environment it only holds it weakly, otherwise the two would
+/// keep each other alive after the environment goes out of scope.
+#[derive(Clone, Debug)]
+pub enum Scope {
+    Strong(Rc<RefCell<Environment>>),
+    Weak(Weak<RefCell<Environment>>),
+}
+
+impl Scope {
+    pub fn upgrade(&self) -> Option<Rc<RefCell<Environment>>> {
+        match self {
+            Self::Strong(env) => Some(env.clone()),
+            Self::Weak(env) => env.upgrade(),
+        }
+    }
+
+    fn as_ptr(&self) -> *const RefCell<Environment> {
+        match self {
+            Self::Strong(env) => Rc::as_ptr(env),
+            Self::Weak(env) => env.as_ptr(),
+        }
+    }
+}
+
+/// An object created from a class. Its fields and methods live in `env`,
+/// which is shared by every copy of the value.
+#[derive(Debug)]
+pub struct Instance {
+    pub class: String,
+    pub env: Rc<RefCell<Environment>>,
+}
+
+impl Value {
+    /// Whether the value can have type `ty`. Type variables match anything
+    /// and functions are only checked to be callable.
+    pub fn has_type(&self, ty: &Type) -> bool {
+        match (self, ty) {
+            (_, Type::Var(_)) => true,
+            (Self::Int(_), Type::Primitive(Primitive::Int))
+            | (Self::Float(_), Type::Primitive(Primitive::Float))
+            | (Self::Bool(_), Type::Primitive(Primitive::Bool))
+            | (Self::Str(_), Type::Primitive(Primitive::Str))
+            | (Self::Nil, Type::Primitive(Primitive::Unit)) => true,
+            (Self::List(items), Type::Primitive(Primitive::List(ty))) => {
+                items.iter().all(|item| item.has_type(ty))
+            }
+            (Self::Instance(instance), Type::Class(name)) => &instance.class == name,
+            (
+                Self::BuiltinFunction(_) | Self::NativeFunction(_) | Self::Function(..),
+                Type::Function(..),
+            ) => true,
+            _ => false,
+        }
+    }
+
+    /// The value to store in `env`, where functions and classes created in
+    /// `env` hold it weakly.
+    pub fn stored_in(self, env: &Environment) -> Value {
+        let created_in_env = |scope: &Rc<RefCell<Environment>>| std::ptr::eq(scope.as_ptr(), env);
+        match self {
+            Self::Function(params, body, Scope::Strong(scope)) if created_in_env(&scope) => {
+                Self::Function(params, body, Scope::Weak(Rc::downgrade(&scope)))
+            }
+            Self::Class(name, params, body, Scope::Strong(scope)) if created_in_env(&scope) => {
+                Self::Class(name, params, body, Scope::Weak(Rc::downgrade(&scope)))
+            }
+            Self::List(items) => Self::List(items.into_iter().map(|v| v.stored_in(env)).collect()),
+            v => v,
+        }
+    }
+
+    /// A value read from an environment, holding again the scope it may
+    /// only have held weakly there.
+    pub fn loaded(self) -> Value {
+        match self {
+            Self::Function(params, body, Scope::Weak(scope)) => match scope.upgrade() {
+                Some(scope) => Self::Function(params, body, Scope::Strong(scope)),
+                None => Self::Function(params, body, Scope::Weak(scope)),
+            },
+            Self::Class(name, params, body, Scope::Weak(scope)) => match scope.upgrade() {
+                Some(scope) => Self::Class(name, params, body, Scope::Strong(scope)),
+                None => Self::Class(name, params, body, Scope::Weak(scope)),
+            },
+            Self::List(items) => Self::List(items.into_iter().map(Value::loaded).collect()),
+            v => v,
+        }
+    }
+
+    /// The element at `index` of a list, failing when it's out of range.
+    pub fn element(&mut self, index: i64) -> Result<&mut Value, String> {
+        let list = match self {
+            Self::List(list) => list,
+            v => return Err(format!("'{}' is not a list", v)),
+        };
+        let length = list.len();
+
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| list.get_mut(i))
+            .ok_or_else(|| {
+                format!(
+                    "index {} is out of range for a list of length {}",
+                    index, length
+                )
+            })
+    }
+
+    /// The elements of a list from `start` up to `end`, which is included
+    /// if `inclusive`.
+    pub fn slice(&self, start: i64, end: i64, inclusive: bool) -> Result<Value, String> {
+        let list = match self {
+            Self::List(list) => list,
+            v => return Err(format!("'{}' is not a list", v)),
+        };
+        let out_of_range = || {
+            format!(
+                "slice {}{}{} is out of range for a list of length {}",
+                start,
+                if inclusive { "..=" } else { ".." },
+                end,
+                list.len()
+            )
+        };
+        let past_end = if inclusive {
+            end.checked_add(1)
+        } else {
+            Some(end)
+        };
+
+        match (usize::try_from(start), past_end.map(usize::try_from)) {
+            (Ok(start), Some(Ok(end))) if start <= end && end <= list.len() => {
+                Ok(Self::List(list[start..end].to_vec()))
+            }
+            _ => Err(out_of_range()),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a == b,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Str(a), Self::Str(b)) => a == b,
+            (Self::List(a), Self::List(b)) => a == b,
+            (Self::Function(p1, b1, e1), Self::Function(p2, b2, e2)) => {
+                p1 == p2 && b1 == b2 && e1.as_ptr() == e2.as_ptr()
+            }
+            (Self::NativeFunction(a), Self::NativeFunction(b)) => Rc::ptr_eq(a, b),
+            (Self::Class(a, ..), Self::Class(b, ..)) => a == b,
+            (Self::Instance(a), Self::Instance(b)) => Rc::ptr_eq(a, b),
+            (Self::Nil, Self::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a.partial_cmp(b),
+            (Self::Float(a), Self::Float(b)) => a.partial_cmp(b),
+            (Self::Bool(a), Self::Bool(b)) => a.partial_cmp(b),
+            (Self::Str(a), Self::Str(b)) => a.partial_cmp(b),
+            (Self::List(a), Self::List(b)) => a.partial_cmp(b),
+            (Self::Nil, Self::Nil) => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Int(x) => write!(f, "{}", *x),
+            Self::Float(x) => write!(f, "{:?}", *x),
             Self::Bool(b) => write!(f, "{}", *b),
             Self::List(list) => {
                 let mut dummy: String = String::from("[");
                 let mut values = Vec::new();
                 for v in list {
                     dummy.push_str(v.to_string().as_str());
-                    dummy.push_str(",");
+                    dummy.push(',');
                     values.push(v);
                 }
-                dummy.pop();
-                dummy.push_str("]");
+                if !list.is_empty() {
+                    dummy.pop();
+                }
+                dummy.push(']');
                 write!(f, "{}", dummy)?;
                 Ok(())
             }
             Self::Nil => write!(f, "Nil"),
             Self::Str(s) => write!(f, "{}", *s),
-            _ => unimplemented!(),
+            Self::BuiltinFunction(_) => write!(f, "<builtin function>"),
+            Self::NativeFunction(_) => write!(f, "<native function>"),
+            Self::Function(..) => write!(f, "<function>"),
+            Self::Class(name, ..) => write!(f, "<class {}>", name),
+            Self::Instance(instance) => {
+                let env = instance.env.borrow();
+                let mut fields: Vec<_> = env
+                    .vals
+                    .iter()
+                    .filter(|(_, v)| !matches!(v, Value::Function(..)))
+                    .map(|(name, v)| format!("{}: {}", name, v))
+                    .collect();
+                fields.sort();
+                write!(f, "{} {{ {} }}", instance.class, fields.join(", "))
+            }
+        }
+    }
+}
+
+impl std::ops::Add for Value {
+    type Output = Result<Self, String>;
+    fn add(self, other: Value) -> Self::Output {
+        match (self, other) {
+            (Self::Int(x), Self::Int(y)) => x
+                .checked_add(y)
+                .map(Value::Int)
+                .ok_or_else(|| format!("{} + {} overflows", x, y)),
+            (Self::Str(x), Self::Str(y)) => Ok(Value::Str(x + &y)),
+            (Self::Float(x), Self::Float(y)) => Ok(Value::Float(x + y)),
+            (x, y) => Err(format!("unsupported operands '{}' and '{}' for `+`", x, y)),
         }
     }
 }
 
 impl std::ops::Sub for Value {
-    type Output = Self;
-    fn sub(self, other: Value) -> Self {
-        match self {
-            Self::Int(x) => match other {
-                Value::Int(y) => Value::Int(x - y),
-                _ => unreachable!(),
-            },
-            _ => unreachable!(),
+    type Output = Result<Self, String>;
+    fn sub(self, other: Value) -> Self::Output {
+        match (self, other) {
+            (Self::Int(x), Self::Int(y)) => x
+                .checked_sub(y)
+                .map(Value::Int)
+                .ok_or_else(|| format!("{} - {} overflows", x, y)),
+            (Self::Float(x), Self::Float(y)) => Ok(Value::Float(x - y)),
+            (x, y) => Err(format!("unsupported operands '{}' and '{}' for `-`", x, y)),
+        }
+    }
+}
+
+impl std::ops::Mul for Value {
+    type Output = Result<Self, String>;
+    fn mul(self, other: Value) -> Self::Output {
+        match (self, other) {
+            (Self::Int(x), Self::Int(y)) => x
+                .checked_mul(y)
+                .map(Value::Int)
+                .ok_or_else(|| format!("{} * {} overflows", x, y)),
+            (Self::Float(x), Self::Float(y)) => Ok(Value::Float(x * y)),
+            (x, y) => Err(format!("unsupported operands '{}' and '{}' for `*`", x, y)),
         }
     }
 }
 
 impl std::ops::Div for Value {
-    type Output = Self;
-    fn div(self, other: Value) -> Self {
+    type Output = Result<Self, String>;
+    fn div(self, other: Value) -> Self::Output {
+        match (self, other) {
+            (Self::Int(_), Self::Int(0)) => Err("division by zero".to_string()),
+            (Self::Int(x), Self::Int(y)) => x
+                .checked_div(y)
+                .map(Value::Int)
+                .ok_or_else(|| format!("{} / {} overflows", x, y)),
+            (Self::Float(x), Self::Float(y)) => Ok(Value::Float(x / y)),
+            (x, y) => Err(format!("unsupported operands '{}' and '{}' for `/`", x, y)),
+        }
+    }
+}
+
+impl std::ops::Rem for Value {
+    type Output = Result<Self, String>;
+    fn rem(self, other: Value) -> Self::Output {
+        match (self, other) {
+            (Self::Int(_), Self::Int(0)) => Err("division by zero".to_string()),
+            (Self::Int(x), Self::Int(y)) => x
+                .checked_rem(y)
+                .map(Value::Int)
+                .ok_or_else(|| format!("{} % {} overflows", x, y)),
+            (Self::Float(x), Self::Float(y)) => Ok(Value::Float(x % y)),
+            (x, y) => Err(format!("unsupported operands '{}' and '{}' for `%`", x, y)),
+        }
+    }
+}
+
+impl std::ops::Neg for Value {
+    type Output = Result<Self, String>;
+    fn neg(self) -> Self::Output {
         match self {
-            Self::Int(x) => match other {
-                Value::Int(y) => Value::Int(x / y),
-                _ => unreachable!(),
-            },
-            _ => unreachable!(),
+            Self::Int(x) => x
+                .checked_neg()
+                .map(Value::Int)
+                .ok_or_else(|| format!("-({}) overflows", x)),
+            Self::Float(x) => Ok(Value::Float(-x)),
+            x => Err(format!("unsupported operand '{}' for `-`", x)),
         }
     }
 }
 
-impl std::ops::Add for Value {
+impl std::ops::Not for Value {
     type Output = Self;
-    fn add(self, other: Value) -> Self {
+    fn not(self) -> Self {
         match self {
-            Self::Int(x) => match other {
-                Value::Int(y) => Value::Int(x + y),
-                _ => unreachable!(),
-            },
+            Self::Bool(b) => Value::Bool(!b),
             _ => unreachable!(),
         }
     }
@@ -0,0 +1,43 @@
+pub mod ast;
+pub mod builtin;
+pub mod engine;
+pub mod environment;
+pub mod error;
+pub mod interpreter;
+mod tests;
+pub mod type_checker;
+use ast::*;
+use environment::*;
+use interpreter::*;
+
+pub use ast::{Primitive, Type};
+pub use engine::Engine;
+pub use error::Error;
+pub use interpreter::Value;
+
+#[macro_use]
+extern crate lalrpop_util;
+
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    #[allow(clippy::pedantic)]
+    #[allow(dead_code)]
+    #[allow(unused_imports)]
+    parser
+);
+
+/// Runs the script at `path`, rendering any error against its source.
+pub fn run_file(path: &str) -> Result<Value, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("{}: unable to read file: {}", path, e))?;
+    let mut engine = Engine::new();
+    engine.check(&source).map_err(|errors| {
+        errors
+            .iter()
+            .map(|e| e.render(path, &source))
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+
+    engine.eval(&source).map_err(|e| e.render(path, &source))
+}
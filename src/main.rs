@@ -1,26 +1,7 @@
 use clap::Parser;
-pub mod ast;
-pub mod builtin;
-pub mod environment;
-pub mod error;
-pub mod interpreter;
-pub mod repl;
-pub mod tests;
-pub mod type_checker;
-use ast::*;
-use environment::*;
-use interpreter::*;
+use martta::run_file;
 use repl::*;
-
-#[macro_use]
-extern crate lalrpop_util;
-
-lalrpop_mod!(
-    #[allow(clippy::all)]
-    #[allow(clippy::pedantic)]
-    #[allow(dead_code)]
-    parser
-);
+mod repl;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -37,6 +18,11 @@ fn main() {
 
     match args.file {
         None => Repl::run(),
-        Some(_) => println!("file handler"),
+        Some(path) => {
+            if let Err(e) = run_file(&path) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
     }
 }
@@ -1,24 +1,11 @@
-use crate::environment::*;
-use crate::interpreter::Interpreter;
-use crate::type_checker::*;
-use std::cell::RefCell;
+use martta::Engine;
 use std::io::Write;
-use std::rc::Rc;
-
-lalrpop_mod!(
-    #[allow(clippy::all)]
-    #[allow(clippy::pedantic)]
-    #[allow(dead_code)]
-    parser
-);
 
 pub struct Repl {}
 
 impl Repl {
     pub fn run() {
-        let env = Environment::default();
-        let mut tc = Typechecker::default();
-        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let mut engine = Engine::new();
         loop {
             print!(":> ");
             std::io::stdout().flush().unwrap();
@@ -29,15 +16,17 @@ impl Repl {
             if line.is_empty() || line.contains(":q") {
                 break;
             }
-            match parser::ProgParser::new().parse(&line) {
-                Ok(ast) => match tc.typecheck(&ast) {
-                    Ok(val) => match interpreter.run(&val) {
-                        Ok(res) => println!("{}", res),
-                        Err(e) => println!("{}", e),
-                    },
-                    Err(tc_error) => println!("{}", tc_error),
-                },
-                Err(parse_error) => println!("{}", parse_error),
+            // Spans point into the whole session, earlier lines included.
+            if let Err(errors) = engine.check(&line) {
+                let session = format!("{}{}", engine.source(), line);
+                for error in errors {
+                    println!("{}", error.render("<repl>", &session))
+                }
+                continue;
+            }
+            match engine.eval(&line) {
+                Ok(res) => println!("{}", res),
+                Err(e) => println!("{}", e.render("<repl>", engine.source())),
             }
         }
     }
@@ -1,10 +1,22 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
+    use crate::error::Error;
     use crate::type_checker::*;
     use crate::*;
     use std::cell::RefCell;
     use std::rc::Rc;
 
+    /// Runs a well-typed program in a fresh environment.
+    fn run(input: &str) -> Result<Value, Error> {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        interpreter.run(&tc_value)
+    }
+
     #[test]
     fn anonymous_fn() {
         let mut env = Environment::default();
@@ -15,12 +27,12 @@ mod tests {
         )
         .unwrap();
         let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
-        let input = "let a: int = | n | => {
+        let input = "let a: (int) -> int = | n: int | => {
             return n + 1
         };
 
         a(4);";
-        let source = parser::ProgParser::new().parse(&input).unwrap();
+        let source = parser::ProgParser::new().parse(input).unwrap();
         let tc_value = tc.typecheck(&source).unwrap();
         let res = interpreter.run(&tc_value).unwrap();
 
@@ -42,7 +54,7 @@ mod tests {
 
         let n: int = 8;
         sum(n);";
-        let source = parser::ProgParser::new().parse(&input).unwrap();
+        let source = parser::ProgParser::new().parse(input).unwrap();
         let tc_value = tc.typecheck(&source).unwrap();
         let res = interpreter.run(&tc_value).unwrap();
 
@@ -57,7 +69,7 @@ mod tests {
         let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
 
         let input = "true && 2";
-        let source = parser::ProgParser::new().parse(&input).unwrap();
+        let source = parser::ProgParser::new().parse(input).unwrap();
 
         let tc_value = tc.typecheck(&source).unwrap();
         let res = interpreter.run(&tc_value).unwrap();
@@ -66,7 +78,6 @@ mod tests {
     }
 
     #[test]
-    #[ignore = "Stopped working somehow."]
     fn re_assignment() {
         let mut env = Environment::default();
         let mut tc = Typechecker::default();
@@ -77,7 +88,7 @@ mod tests {
         .unwrap();
         let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
         let input = "
-            let a = 1;
+            let mut a: int = 1;
 
             a = 4;
             println(a);";
@@ -229,10 +240,1517 @@ mod tests {
     } else {
     println(86);
     }";
-        let source = parser::ProgParser::new().parse(&input).unwrap();
+        let source = parser::ProgParser::new().parse(input).unwrap();
         let tc_value = tc.typecheck(&source).unwrap();
         let res = interpreter.run(&tc_value).unwrap();
 
         assert_eq!(res, Value::Int(86));
     }
+
+    #[test]
+    fn run_file_script() {
+        let res = crate::run_file("src/tests/anonymous_functions.mrt").unwrap();
+
+        assert_eq!(res, Value::Int(5));
+    }
+
+    #[test]
+    fn run_file_reports_file_name() {
+        let err = crate::run_file("src/tests/missing.mrt").unwrap_err();
+
+        assert!(err.starts_with("src/tests/missing.mrt:"));
+    }
+
+    #[test]
+    fn typecheck_collects_all_errors() {
+        let mut tc = Typechecker::default();
+        let input = "let x: int = true;
+        if 3 { 1 }
+        fn f n => bool { n + 1 }";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            errors[0],
+            Error::TypeError("expected `int`, found `bool`".into(), Span::new(13, 17))
+        );
+    }
+
+    #[test]
+    fn runtime_error_traceback() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn boom n => int { n + reduce [] }
+        fn sum n => int {
+            if n < 2 {
+                return boom(n);
+            } else {
+                return sum(n-1);
+            }
+        }
+        sum(2);";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let err = interpreter.run(&tc_value).unwrap_err();
+
+        match err {
+            Error::Traceback(e, frames) => {
+                assert_eq!(
+                    *e,
+                    Error::InvalidOperation(
+                        "cannot reduce an empty list".into(),
+                        Span::new(23, 32)
+                    )
+                );
+                let names: Vec<&str> = frames.iter().map(|f| f.function.as_str()).collect();
+                assert_eq!(names, vec!["sum", "sum", "boom"]);
+            }
+            e => panic!("expected a traceback, got {:?}", e),
+        }
+        assert!(interpreter.call_stack.is_empty());
+    }
+
+    #[test]
+    fn while_false_is_skipped() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let a: int = 1;
+        while a < 1 {
+            a + 1;
+        }";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Nil);
+    }
+
+    #[test]
+    fn while_condition_must_be_bool() {
+        let mut tc = Typechecker::default();
+        let input = "while 1 { 2; }";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![Error::TypeError(
+                "expected `bool`, found `int`".into(),
+                Span::new(6, 7)
+            )]
+        );
+    }
+
+    #[test]
+    fn while_updates_enclosing_binding() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let mut i: int = 0;
+        let mut total: int = 0;
+        while i < 5 {
+            let step: int = i;
+            total = total + step;
+            i = i + 1;
+        }
+        total;";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(10));
+        assert_eq!(interpreter.env.borrow_mut().get_var("step".into()), None);
+    }
+
+    #[test]
+    fn immutable_re_assignment() {
+        let mut tc = Typechecker::default();
+        let input = "let a: int = 1;
+        a = 2;
+        b = 3;";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                Error::TypeError(
+                    "cannot assign twice to immutable variable `a`".into(),
+                    Span::new(24, 29)
+                ),
+                Error::TypeError(
+                    "cannot assign to undeclared variable `b`".into(),
+                    Span::new(39, 44)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn class_script() {
+        let res = crate::run_file("src/tests/class.mrt").unwrap();
+
+        assert_eq!(res, Value::Int(4));
+    }
+
+    #[test]
+    fn instances_share_state() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "class Point(x: int, y: int) {
+            let mut moves: int = 0;
+            def shift(dx) => int {
+                moves = moves + 1;
+                x + dx
+            }
+        }
+        let p: Point = new Point(1, 2);
+        let q: Point = p;
+        q.shift(3);
+        p.moves + p.y;";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(3));
+    }
+
+    #[test]
+    fn class_type_errors() {
+        let mut tc = Typechecker::default();
+        let input = "class Point(x: int, y: int) {
+            def sum() => int { x + y }
+        }
+        let p: Point = Point(true, 2);
+        p.z;
+        p.sum(1);";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `int`, found `bool`",
+                "Types mismatch: no field `z` on type `Point`",
+                "Types mismatch: `sum` takes 0 arguments but 1 were supplied",
+            ]
+        );
+    }
+
+    #[test]
+    fn typed_parameters() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn add(a: int, b: int) => int { a + b }
+        fn twice(f: (int) -> int, x: int) => int { f(f(x)) }
+        let inc: (int) -> int = |n: int| => { add(n, 1) };
+        twice(inc, 3);";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(5));
+    }
+
+    #[test]
+    fn call_checked_against_signature() {
+        let mut tc = Typechecker::default();
+        let input = "fn add(a: int, b: int) => int { a + b }
+        add(1, true);
+        add(1);
+        let f: (bool) -> int = add;";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `int`, found `bool`",
+                "Types mismatch: `add` takes 2 arguments but 1 were supplied",
+                "Types mismatch: expected `(bool) -> int`, found `(int, int) -> int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn inferred_types() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn twice(f, x) => { f(f(x)) }
+        let inc = |n| => { n + 1 };
+        let id = |x| => { x };
+        if id(true) { id(twice(inc, 3)) } else { 0 }";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(5));
+    }
+
+    #[test]
+    fn inference_errors() {
+        let mut tc = Typechecker::default();
+        let input = "let a = y + 1;
+        missing(1);
+        let self_apply = |f| => { f(f) };
+        let xs = [];
+        let ys: [bool] = xs;
+        let n: int = reduce xs;";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[..2],
+            [
+                "Types mismatch: cannot find value `y` in this scope",
+                "Types mismatch: cannot find function `missing` in this scope",
+            ]
+        );
+        assert!(messages[2].contains("infinite type"));
+    }
+
+    #[test]
+    fn closures_capture_their_scope() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn make_adder(n: int) => (int) -> int { |x| => { x + n } }
+        fn make_counter() => () -> int {
+            let mut count = 0;
+            | | => {
+                count = count + 1;
+                count
+            }
+        }
+        let x = 1;
+        fn get() => int { x }
+        fn shadow() => int {
+            let x = 10;
+            get()
+        }
+        let add2 = make_adder(2);
+        let counter = make_counter();
+        let other = make_counter();
+        counter();
+        counter();
+        other();
+        add2(counter()) + shadow()";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(6));
+    }
+
+    #[test]
+    fn early_return() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn first_over(limit: int) => int {
+            let mut n = 0;
+            while true {
+                if limit < n {
+                    return n;
+                }
+                n = n + 3;
+            }
+            return 0;
+        }
+        let abs = |n: int| => {
+            if n < 0 {
+                return 0 - n;
+            }
+            n
+        };
+        first_over(10) + abs(0 - 4)";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(16));
+    }
+
+    #[test]
+    fn return_type_errors() {
+        let mut tc = Typechecker::default();
+        let input = "fn f(n: int) => int {
+            if n < 0 {
+                return true;
+            }
+            n
+        }
+        return 1;";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `int`, found `bool`",
+                "Types mismatch: `return` outside of a function",
+            ]
+        );
+    }
+
+    #[test]
+    fn break_and_continue() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let mut total = 0;
+        let mut i = 0;
+        outer@ while true {
+            i = i + 1;
+            if 4 < i {
+                break;
+            }
+            if i == 2 {
+                continue;
+            }
+            let mut j = 0;
+            while true {
+                j = j + 1;
+                if i == 3 {
+                    continue@outer;
+                }
+                if i == 4 {
+                    break@outer;
+                }
+                if 2 < j {
+                    break;
+                }
+                total = total + j;
+            }
+        }
+        total";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(3));
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        let mut tc = Typechecker::default();
+        let input = "break;
+        while true {
+            let f = |x| => {
+                continue;
+                x
+            };
+            break@outer;
+        }";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: `break` outside of a loop",
+                "Types mismatch: `continue` outside of a loop",
+                "Types mismatch: use of undeclared label `outer`",
+            ]
+        );
+    }
+
+    #[test]
+    fn for_loops() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let mut total = 0;
+        for x in [1, 2, 3] {
+            total = total + x;
+        }
+        for i in 0..3 {
+            total = total + i;
+        }
+        outer@ for i in 1..=10 {
+            for flag in [true, false] {
+                if 2 < i {
+                    break@outer;
+                }
+                if flag {
+                    continue;
+                }
+                total = total + 100;
+            }
+        }
+        for x in [] {
+            total = total + x;
+        }
+        total";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(209));
+    }
+
+    #[test]
+    fn for_loop_types() {
+        let mut tc = Typechecker::default();
+        let input = "for b in [true] {
+            let n: int = b;
+        }
+        for i in 0..true {
+            i
+        }
+        for x in 5 {
+            x
+        }";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `int`, found `bool`",
+                "Types mismatch: expected `int`, found `bool`",
+                "Types mismatch: cannot iterate over a value of type `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn arithmetic_and_logic_operators() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let mut total = 0;
+        for i in 1..=10 {
+            if i % 2 != 0 {
+                let tripled = i * 3;
+                total += tripled;
+            }
+        }
+        let a = -total;
+        let positive = 0 >= total;
+        let ok = !positive;
+        if ok { a } else { 0 }";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(-75));
+    }
+
+    #[test]
+    fn operator_type_errors() {
+        let mut tc = Typechecker::default();
+        let input = "let a = 1;
+        a += 1;
+        let b = !3;
+        let c = -true;
+        let d = 1 != true;";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: cannot assign twice to immutable variable `a`",
+                "Types mismatch: expected `bool`, found `int`",
+                "Types mismatch: expected `int`, found `bool`",
+                "Types mismatch: expected `int`, found `bool`",
+            ]
+        );
+    }
+
+    #[test]
+    fn operator_precedence() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let mut total = 0;
+        total += 2 + 3 * 4;
+        for i in 0..total - 12 {
+            total += i;
+        }
+        total";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(15));
+    }
+
+    #[test]
+    fn parenthesised_condition() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let a = 1 + 2 < 3 + 1 && true;
+        let b = -2 * -(1 + 2) - 10 % 4 / 2;
+        let c = !(1 < 2) || 1 != 2 == true;
+        a && c && (b == 5)";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Bool(true));
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        assert_eq!(
+            run("3000000000 * 3000000000"),
+            Ok(Value::Int(9_000_000_000_000_000_000))
+        );
+        assert_eq!(
+            run("let a = 1; a / (a - 1)"),
+            Err(Error::InvalidOperation(
+                "division by zero".into(),
+                Span::new(11, 22)
+            ))
+        );
+        assert_eq!(
+            run("9223372036854775807 + 1"),
+            Err(Error::InvalidOperation(
+                "9223372036854775807 + 1 overflows".into(),
+                Span::new(0, 23)
+            ))
+        );
+        assert!(matches!(
+            run("let a = 0; 7 % a"),
+            Err(Error::InvalidOperation(..))
+        ));
+        assert!(matches!(
+            run("reduce [9223372036854775807, 1]"),
+            Err(Error::InvalidOperation(..))
+        ));
+    }
+
+    #[test]
+    fn integer_literal_too_large() {
+        let input = "let a = 99999999999999999999;";
+        let err = parser::ProgParser::new().parse(input).unwrap_err();
+
+        assert_eq!(
+            Error::from(err),
+            Error::ParsingError(
+                "integer literal `99999999999999999999` is too large".into(),
+                Span::new(8, 28)
+            )
+        );
+    }
+
+    #[test]
+    fn floats() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn mean(xs: [float]) => float {
+            let mut total = 0.0;
+            let mut count = 0;
+            for x in xs {
+                total += x;
+                count += 1;
+            }
+            total / to_float(count)
+        }
+        let m: float = mean([1.5, 2.5, -0.5]);
+        let ok = 1.0 < m && m <= 1.25;
+        if ok { to_int(m * 10.0) % 7 } else { 0 }";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(4));
+    }
+
+    #[test]
+    fn float_type_errors() {
+        let mut tc = Typechecker::default();
+        let input = "let a = 1.0 + 2;
+        let b = 1 < 2.0;
+        let c: int = to_float(1);";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `float`, found `int`",
+                "Types mismatch: expected `int`, found `float`",
+                "Types mismatch: expected `int`, found `float`",
+            ]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = r#"let name = "Martta";
+        let mut greeting = 'it\'s ' + name;
+        greeting += "\t\"ok\"\n";
+        let n = 2;
+        s"${greeting}${n} + ${n * 2} = ${n + n * 2}, \${literal} ${name + '!'}""#;
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(
+            res,
+            Value::Str("it's Martta\t\"ok\"\n2 + 4 = 6, ${literal} Martta!".into())
+        );
+    }
+
+    #[test]
+    fn string_type_errors() {
+        let mut tc = Typechecker::default();
+        let input = r#"let a = 'n = ' + 1;
+        let b = s"${1 + true}";"#;
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                Error::TypeError("expected `string`, found `int`".into(), Span::new(17, 18)),
+                Error::TypeError("expected `int`, found `bool`".into(), Span::new(44, 48)),
+            ]
+        );
+        assert!(parser::ProgParser::new().parse(r#"s"${1 +}""#).is_err());
+        assert!(parser::ProgParser::new().parse(r#"'\q'"#).is_err());
+    }
+
+    #[test]
+    fn string_builtins() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = r#"let line = "  Name: Ada Lovelace ;age: 36  ";
+        let mut fields = [];
+        for field in split(trim(line), ";") {
+            fields = [field];
+            if starts_with(field, "age") {
+                let age = parse_int(substring(field, 5, len(field)));
+                fields = [to_upper(substring(field, 0, 3)), to_string(age + 1)];
+            }
+        }
+        let name = replace(trim(substring(line, 8, 21)), " ", "_");
+        if contains(name, "Ada") {
+            join(fields, "=") + " " + to_lower(name)
+        } else {
+            ""
+        }"#;
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Str("AGE=37 ada_lovelace".into()));
+    }
+
+    #[test]
+    fn string_builtin_errors() {
+        assert_eq!(
+            run("parse_int('12a')"),
+            Err(Error::InvalidOperation(
+                "'12a' is not a valid int".into(),
+                Span::new(0, 16)
+            ))
+        );
+        assert!(run("substring('abc', 2, 4)").is_err());
+
+        let mut tc = Typechecker::default();
+        let source = parser::ProgParser::new().parse("len(3)").unwrap();
+        assert!(tc.typecheck(&source).is_err());
+    }
+
+    #[test]
+    fn builtins_are_registered_and_typed() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "print('total: ');
+        println(1 + 2) * to_int(2.5)";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(6));
+
+        let mut tc = Typechecker::default();
+        let input = "println('a') + 1;
+        print(1, 2);";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `string`, found `int`",
+                "Types mismatch: `print` takes 1 arguments but 2 were supplied",
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_lists() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn flatten(xss: [[string]]) => [string] {
+            let mut flat = [];
+            for xs in xss {
+                for x in xs {
+                    flat = [join([join(flat, ''), x], '')];
+                }
+            }
+            flat
+        }
+        let empty: [[int]] = [[]];
+        let grid: [[int]] = [[1, 2], [], [3]];
+        let fs: [(int) -> int] = [|x| => { x + 1 }];
+        let words = flatten([['a', 'b'], [], ['c']]);
+        let mut total = 0;
+        for row in grid {
+            for f in fs {
+                total += f(reduce [0, 0]);
+            }
+        }
+        s\"${words} ${empty} ${grid} ${total}\"";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Str("[abc] [[]] [[1,2],[],[3]] 3".into()));
+    }
+
+    #[test]
+    fn nested_list_type_errors() {
+        let mut tc = Typechecker::default();
+        let input = "let a: [[int]] = [[true]];
+        let b: [[string]] = ['x'];
+        let c = [[1], [false]];";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `[[int]]`, found `[[bool]]`",
+                "Types mismatch: expected `[[string]]`, found `[string]`",
+                "Types mismatch: expected `[int]`, found `[bool]`",
+            ]
+        );
+    }
+
+    #[test]
+    fn list_indexing() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let mut xs = [10, 20, 30, 40];
+        let mut grid = [[1, 2], [3, 4]];
+        xs[0] = xs[3] + 1;
+        xs[1] += 5
+        grid[1][0] = 7;
+        let words = split('a b c', ' ');
+        let fst = words[0];
+        s\"${xs} ${xs[1..3]} ${xs[1..=3]} ${xs[4..4]} ${grid} ${grid[0][1]} ${fst}\"";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(
+            res,
+            Value::Str("[41,25,30,40] [25,30] [25,30,40] [] [[1,2],[7,4]] 2 a".into())
+        );
+    }
+
+    #[test]
+    fn list_index_errors() {
+        assert_eq!(
+            run("let xs = [1, 2]; xs[2]"),
+            Err(Error::InvalidOperation(
+                "index 2 is out of range for a list of length 2".into(),
+                Span::new(17, 22)
+            ))
+        );
+        assert_eq!(
+            run("let mut xs = [1]; xs[0 - 1] = 3"),
+            Err(Error::InvalidOperation(
+                "index -1 is out of range for a list of length 1".into(),
+                Span::new(18, 31)
+            ))
+        );
+        assert_eq!(
+            run("let xs = [1, 2]; xs[1..=2]"),
+            Err(Error::InvalidOperation(
+                "slice 1..=2 is out of range for a list of length 2".into(),
+                Span::new(17, 26)
+            ))
+        );
+        assert_eq!(
+            run("let xs = [1, 2]; xs[2..1]"),
+            Err(Error::InvalidOperation(
+                "slice 2..1 is out of range for a list of length 2".into(),
+                Span::new(17, 25)
+            ))
+        );
+
+        let mut tc = Typechecker::default();
+        let input = "let xs = [1, 2];
+        let a: bool = xs[0];
+        let b = xs[true];
+        let c = 3[0];
+        xs[0] = 1;
+        let mut ys = ['a'];
+        ys[0] = 2;
+        f(1)[0] = 2;";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `bool`, found `int`",
+                "Types mismatch: expected `int`, found `bool`",
+                "Types mismatch: cannot index into a value of type `int`",
+                "Types mismatch: cannot assign twice to immutable variable `xs`",
+                "Types mismatch: expected `string`, found `int`",
+                "Types mismatch: cannot find function `f` in this scope",
+            ]
+        );
+    }
+
+    #[test]
+    fn higher_order_builtins() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn double(x: int) => int { x * 2 }
+        let xs = [3, 1, 4, 1, 5];
+        let doubled = map(xs, double);
+        let labels = map(xs, |x| => { to_string(x) });
+        let odd = filter(xs, |x| => { x % 2 == 1 });
+        let product = fold(xs, 1, |acc, x| => { acc * x });
+        let text = fold(labels, '', |acc, x| => { acc + x });
+        let sorted = sort_by(xs, |a, b| => { a - b });
+        let by_length = sort_by(['ccc', 'a', 'bb', 'd'], |a, b| => { len(a) - len(b) });
+        let big = find(xs, |x| => { x > 3 });
+        let none = find(xs, |x| => { x > 5 });
+        let checks = [any(xs, |x| => { x == 4 }), all(xs, |x| => { x > 1 }), all([], |x| => { x })];
+        s\"${doubled} ${odd} ${product} ${text} ${sorted} ${by_length} ${big} ${none} ${checks}\"";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(
+            res,
+            Value::Str(
+                "[6,2,8,2,10] [3,1,1,5] 60 31415 [1,1,3,4,5] [a,d,bb,ccc] [4] [] [true,false,true]"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn higher_order_builtin_errors() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "map([1, 0], |x| => { 1 / x })";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let err = interpreter.run(&tc_value).unwrap_err();
+
+        match err {
+            Error::Traceback(e, frames) => {
+                assert_eq!(
+                    *e,
+                    Error::InvalidOperation("division by zero".into(), Span::new(21, 26))
+                );
+                let names: Vec<&str> = frames.iter().map(|f| f.function.as_str()).collect();
+                assert_eq!(names, vec!["map"]);
+            }
+            e => panic!("expected a traceback, got {:?}", e),
+        }
+        assert!(interpreter.call_stack.is_empty());
+
+        let mut tc = Typechecker::default();
+        let input = "let a: [string] = map([1], |x| => { x + 1 });
+        filter([1], |x| => { x });
+        fold([1], '', |acc, x| => { acc + x });
+        sort_by(['a'], |a, b| => { a == b });";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `[string]`, found `[int]`",
+                "Types mismatch: expected `(int) -> bool`, found `(int) -> int`",
+                "Types mismatch: expected `string`, found `int`",
+                "Types mismatch: expected `(string, string) -> int`, found `(string, string) -> bool`",
+            ]
+        );
+    }
+
+    #[test]
+    fn native_functions() {
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counter = calls.clone();
+        let mut env = Environment::default();
+        env.register_fn(
+            "record",
+            Type::Function(
+                vec![Type::Primitive(Primitive::Str)],
+                Box::new(Type::Primitive(Primitive::Int)),
+            ),
+            move |_, vals| {
+                counter.set(counter.get() + 1);
+                match &vals[0] {
+                    Value::Str(s) if s.is_empty() => Err(Error::InvalidOperation(
+                        "cannot record an empty string".into(),
+                        Span::default(),
+                    )),
+                    Value::Str(s) => Ok(Value::Int(s.len() as i64 + counter.get())),
+                    _ => unreachable!(),
+                }
+            },
+        )
+        .unwrap();
+        env.register_fn(
+            "twice",
+            Type::Function(
+                vec![
+                    Type::Function(vec![Type::Var(0)], Box::new(Type::Var(0))),
+                    Type::Var(0),
+                ],
+                Box::new(Type::Var(0)),
+            ),
+            |interpreter, mut vals| {
+                let x = vals.pop().unwrap();
+                let f = vals.pop().unwrap();
+                let once = interpreter.apply(f.clone(), vec![x])?;
+                interpreter.apply(f, vec![once])
+            },
+        )
+        .unwrap();
+
+        let mut tc = Typechecker::with_environment(&env);
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let a = record('abc');
+        let b = twice(|s: string| => { s + '!' }, 'hi');
+        s\"${a + record('x')} ${b} ${twice(|x| => { x * 3 }, 2)}\"";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Str("7 hi!! 18".into()));
+        assert_eq!(calls.get(), 2);
+
+        let input = "record('')";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+
+        assert_eq!(
+            interpreter.run(&tc_value),
+            Err(Error::InvalidOperation(
+                "cannot record an empty string".into(),
+                Span::new(0, 10)
+            ))
+        );
+
+        let input = "record(1) + twice(|x| => { x }, true)";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `string`, found `int`",
+                "Types mismatch: expected `int`, found `bool`",
+            ]
+        );
+    }
+
+    #[test]
+    fn engine_keeps_state_between_calls() {
+        let mut engine = Engine::new();
+
+        assert_eq!(
+            engine.eval("let mut total = 1; fn add(n) => { total += n; total }"),
+            Ok(Value::Nil)
+        );
+        assert_eq!(engine.eval("add(2)"), Ok(Value::Int(3)));
+        assert_eq!(engine.eval("add(4); total"), Ok(Value::Int(7)));
+
+        // Checking doesn't define anything, and neither does a failed eval.
+        assert_eq!(engine.check("let fresh = 1; fresh + total"), Ok(()));
+        assert!(engine.eval("let broken = 1; broken + true").is_err());
+        let errors = engine
+            .check("fresh; broken")
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                "Types mismatch: cannot find value `fresh` in this scope",
+                "Types mismatch: cannot find value `broken` in this scope",
+            ]
+        );
+        assert!(matches!(
+            engine.eval("total ="),
+            Err(Error::ParsingError(..))
+        ));
+    }
+
+    #[test]
+    fn engine_native_functions() {
+        let mut engine = Engine::new();
+        engine
+            .register_fn(
+                "double",
+                Type::Function(
+                    vec![Type::Primitive(Primitive::Int)],
+                    Box::new(Type::Primitive(Primitive::Int)),
+                ),
+                |_, vals| match vals[0] {
+                    Value::Int(x) => Ok(Value::Int(x * 2)),
+                    _ => unreachable!(),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            engine.eval("map([1, 2], double)"),
+            Ok(Value::List(vec![Value::Int(2), Value::Int(4)]))
+        );
+        assert_eq!(
+            engine.eval("double('a')"),
+            Err(Error::TypeError(
+                "expected `int`, found `string`".into(),
+                Span::new(27, 30)
+            ))
+        );
+    }
+
+    #[test]
+    fn call_results_are_not_generalised() {
+        let mut tc = Typechecker::default();
+        let input = "fn mk() => { let mut x = []; |v| => { let old = x; x = [v]; old } }
+        let f = mk();
+        f(1);
+        f(2);
+        let t = f(\"a\");
+        println(t[0] + \"b\")";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Types mismatch: expected `int`, found `string`",
+                "Types mismatch: expected `int`, found `string`",
+            ]
+        );
+    }
+
+    #[test]
+    fn reduce_reports_unsupported_elements() {
+        assert_eq!(
+            run("println(reduce [true, false])"),
+            Err(Error::InvalidOperation(
+                "unsupported operands 'true' and 'false' for `+`".into(),
+                Span::new(8, 28)
+            ))
+        );
+        assert_eq!(
+            run("reduce [[1], [2]]"),
+            Err(Error::InvalidOperation(
+                "unsupported operands '[1]' and '[2]' for `+`".into(),
+                Span::new(0, 17)
+            ))
+        );
+        assert_eq!(run("reduce ['a', 'b']"), Ok(Value::Str("ab".into())));
+    }
+
+    #[test]
+    fn errors_in_conditions_are_kept() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn g(x: int) => int { 10 / x }
+        if g(0) == 1 { 1 } else { 2 }";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let err = interpreter.run(&tc_value).unwrap_err();
+
+        match err {
+            Error::Traceback(e, frames) => {
+                assert_eq!(
+                    *e,
+                    Error::InvalidOperation("division by zero".into(), Span::new(22, 28))
+                );
+                let names: Vec<&str> = frames.iter().map(|f| f.function.as_str()).collect();
+                assert_eq!(names, vec!["g"]);
+            }
+            e => panic!("expected a traceback, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn engine_forgets_programs_that_fail_at_runtime() {
+        let mut engine = Engine::new();
+
+        assert_eq!(
+            engine.eval("let a = 1 / 0; let b = 2"),
+            Err(Error::InvalidOperation(
+                "division by zero".into(),
+                Span::new(8, 13)
+            ))
+        );
+        assert_eq!(
+            engine.eval("b"),
+            Err(Error::TypeError(
+                "cannot find value `b` in this scope".into(),
+                Span::new(25, 26)
+            ))
+        );
+        assert_eq!(engine.eval("let b = 3; b"), Ok(Value::Int(3)));
+
+        assert_eq!(
+            engine.eval("let b = 'str'; 1 / 0"),
+            Err(Error::InvalidOperation(
+                "division by zero".into(),
+                Span::new(55, 60)
+            ))
+        );
+        assert_eq!(engine.eval("to_float(b)"), Ok(Value::Float(3.0)));
+    }
+
+    #[test]
+    fn methods_see_fields_of_their_class() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "class P(x: int) {
+            let double = x * 2;
+            def plus(o: P) => int { x + o.x + o.double }
+        }
+        P(1).plus(P(20))";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(61));
+    }
+
+    #[test]
+    fn classes_are_scoped() {
+        let mut tc = Typechecker::default();
+        let input = "fn make() => int {
+            class K(v: int) { def get() => int { v } }
+            K(1).get()
+        }
+        K(1);";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            messages,
+            vec!["Types mismatch: cannot find function `K` in this scope"]
+        );
+    }
+
+    #[test]
+    fn statements_starting_with_operators() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "fn f(a: int, b: int) => int { (a + b) * 2 }
+        fn neg(x: int) => int { -x }
+        fn wrap(x: int) => [int] { [x] }
+        fn last(x: int) => int {
+            let y = x;
+            -y
+        }
+        let a = 1
+        -3;
+        (f(1, 2) + neg(4));
+        s\"${wrap(3)} ${last(5)} ${a} ${(f(1, 2) + neg(4))}\"";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        // Without a `;`, `let a = 1` continues on the next line.
+        assert_eq!(res, Value::Str("[3] -5 -2 2".into()));
+    }
+
+    #[test]
+    fn re_assignment_without_semicolons() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let mut i = 0
+        while i < 3 { i = i + 1 }
+        let mut j = 1
+        j = 5
+        i + j";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(8));
+    }
+
+    #[test]
+    fn call_frames_are_freed() {
+        let env = Rc::new(RefCell::new(Environment::default()));
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(env.clone());
+        let input = "fn outer(n: int) => int {
+            let f = |x: int| => { x + n };
+            f(1)
+        }
+        fn named(n: int) => (int) -> int {
+            fn inner(x: int) => int { x * n }
+            inner
+        }
+        let double = named(2);
+        outer(1) + outer(2) + double(5)";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Int(15));
+        // Besides the test and the interpreter, only the frame `double` was
+        // created in holds the global environment, `outer`'s are freed.
+        assert_eq!(Rc::strong_count(&env), 3);
+    }
+
+    #[test]
+    fn native_results_are_checked() {
+        let mut engine = Engine::new();
+        engine
+            .register_fn(
+                "boom",
+                Type::Function(
+                    vec![Type::Primitive(Primitive::Int)],
+                    Box::new(Type::Primitive(Primitive::Int)),
+                ),
+                |_, _| Ok(Value::Bool(true)),
+            )
+            .unwrap();
+
+        assert_eq!(
+            engine.eval("boom(1) + 1"),
+            Err(Error::InvalidOperation(
+                "native function 'boom' returned 'true', expected a value of type int".into(),
+                Span::new(0, 7)
+            ))
+        );
+    }
+
+    #[test]
+    fn interpolated_code_spans() {
+        let mut tc = Typechecker::default();
+        let input = r#"let xs = [1];
+        s"${map(xs, |x| => { x + 'a' })}""#;
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let errors = tc.typecheck(&source).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![Error::TypeError(
+                "expected `int`, found `string`".into(),
+                Span::new(47, 50)
+            )]
+        );
+        let error = parser::ProgParser::new()
+            .parse(r#"s"${1 +}""#)
+            .map_err(Error::from)
+            .unwrap_err();
+        assert_eq!(error.span(), Span::new(7, 7));
+    }
+
+    #[test]
+    fn for_loops_over_ranges_are_lazy() {
+        let env = Environment::default();
+        let mut tc = Typechecker::default();
+        let mut interpreter = Interpreter::new(Rc::new(RefCell::new(env)));
+        let input = "let mut total = 0;
+        for i in 0..9223372036854775807 {
+            if i == 4 { break }
+            total = total + i
+        }
+        let xs = 0..=2;
+        s\"${total} ${xs}\"";
+        let source = parser::ProgParser::new().parse(input).unwrap();
+        let tc_value = tc.typecheck(&source).unwrap();
+        let res = interpreter.run(&tc_value).unwrap();
+
+        assert_eq!(res, Value::Str("6 [0,1,2]".into()));
+    }
+
+    #[test]
+    fn if_bodies_are_scoped() {
+        let mut engine = Engine::new();
+
+        assert_eq!(
+            engine.eval("let x = 'a'; if false { let x = 1; } to_float(x)"),
+            Err(Error::TypeError(
+                "expected `int`, found `string`".into(),
+                Span::new(46, 47)
+            ))
+        );
+        assert_eq!(
+            engine.eval("let y = 1; if true { let y = 'b'; } else { let y = 2.0; } y + 1"),
+            Ok(Value::Int(2))
+        );
+    }
+
+    #[test]
+    fn engine_errors_point_into_earlier_sources() {
+        let mut engine = Engine::new();
+        engine.eval("fn f(n: int) => int { 10 / n }").unwrap();
+        let error = engine
+            .eval("f(0) + len('xéééééééééééééééééé')")
+            .unwrap_err();
+
+        assert_eq!(error.span(), Span::new(22, 28));
+        assert_eq!(
+            error.render("<repl>", engine.source()),
+            "error: Invalid operation: division by zero
+ --> <repl>:1:23
+  |
+1 | fn f(n: int) => int { 10 / n }
+  |                       ^^^^^^
+Traceback (most recent call last):
+  <repl>:2:1, in call to `f`"
+        );
+        assert!(error
+            .render("<repl>", "f(0) + len('xéééééééééééééééééé')")
+            .contains("^"));
+    }
+
+    #[test]
+    fn native_functions_need_function_types() {
+        let mut engine = Engine::new();
+
+        assert_eq!(
+            engine.register_fn("x", Type::Primitive(Primitive::Int), |_, _| {
+                Ok(Value::Int(1))
+            }),
+            Err(Error::TypeError(
+                "`x` must have a function type, not `int`".into(),
+                Span::default()
+            ))
+        );
+        assert_eq!(
+            engine.check("to_float(x)"),
+            Err(vec![Error::TypeError(
+                "cannot find value `x` in this scope".into(),
+                Span::new(9, 10)
+            )])
+        );
+    }
+
+    #[test]
+    fn builtins_reject_wrong_arguments() {
+        let env = Rc::new(RefCell::new(Environment::default()));
+        let mut interpreter = Interpreter::new(env.clone());
+        let mut apply = |name: &str, vals: Vec<Value>| {
+            let builtin = env.borrow_mut().get_var(name.to_string()).unwrap();
+            interpreter.apply(builtin, vals)
+        };
+
+        assert_eq!(
+            apply("to_float", vec![Value::Str("a".into())]),
+            Err(Error::InvalidOperation(
+                "'a' is not an int".into(),
+                Span::default()
+            ))
+        );
+        assert_eq!(
+            apply("to_int", vec![Value::Int(1)]),
+            Err(Error::InvalidOperation(
+                "'1' is not a float".into(),
+                Span::default()
+            ))
+        );
+        assert_eq!(
+            apply("len", vec![Value::Int(1)]),
+            Err(Error::InvalidOperation(
+                "'1' is not a string".into(),
+                Span::default()
+            ))
+        );
+        assert_eq!(
+            apply(
+                "join",
+                vec![Value::List(vec![Value::Bool(true)]), Value::Str(",".into())]
+            ),
+            Err(Error::InvalidOperation(
+                "'true' is not a string".into(),
+                Span::default()
+            ))
+        );
+
+        let not_a_bool = Value::Function(
+            vec!["x".into()],
+            vec![HirExpr::Literal(
+                Literal::Int(1),
+                Type::Primitive(Primitive::Int),
+                Span::default(),
+            )],
+            Scope::Strong(env.clone()),
+        );
+        assert_eq!(
+            apply("filter", vec![Value::List(vec![Value::Int(2)]), not_a_bool]),
+            Err(Error::InvalidOperation(
+                "'1' is not a bool".into(),
+                Span::default()
+            ))
+        );
+        assert_eq!(
+            apply("reduce", vec![Value::Int(2)]),
+            Err(Error::InvalidOperation(
+                "'2' is not a list".into(),
+                Span::default()
+            ))
+        );
+    }
+
+    #[test]
+    fn quoted_braces_in_interpolations() {
+        let mut engine = Engine::new();
+        let input = r#"let xs = ['{', "}"];
+        s"${len('}')} ${xs[1] + "{"} ${map(xs, |x| => { s"<${x}>" })}""#;
+
+        assert_eq!(engine.eval(input), Ok(Value::Str("1 }{ [<{>,<}>]".into())));
+    }
 }
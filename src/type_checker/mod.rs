@@ -1,275 +1,1224 @@
 use crate::ast::*;
+use crate::environment::Environment;
 use crate::error::*;
-use std::collections::HashMap;
+use crate::value::Value;
+use std::collections::{HashMap, HashSet};
 
-lalrpop_mod!(
-    #[allow(clippy::all)]
-    #[allow(clippy::pedantic)]
-    #[allow(dead_code)]
-    parser
-);
+/// A type whose variables in `vars` are universally quantified, so every use
+/// of the binding can pick its own types for them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    pub fn mono(ty: Type) -> Self {
+        Self {
+            vars: Vec::new(),
+            ty,
+        }
+    }
+}
 
 #[derive(Debug, Default, Clone)]
 pub struct Context {
-    pub values: HashMap<String, Type>,
+    pub values: HashMap<String, Scheme>,
+    pub mutable: HashSet<String>,
+    /// Classes declared in this scope.
+    pub classes: HashMap<String, ClassType>,
+    pub enclosing: Option<Box<Context>>,
 }
 
 impl Context {
     pub fn define(&mut self, name: String, type_: Type) -> Result<(), String> {
-        self.values.insert(name, type_);
+        self.define_scheme(name, Scheme::mono(type_))
+    }
+
+    pub fn define_scheme(&mut self, name: String, scheme: Scheme) -> Result<(), String> {
+        self.mutable.remove(&name);
+        self.values.insert(name, scheme);
         Ok(())
     }
 
-    pub fn lookup(&mut self, name: String) -> Option<Type> {
+    pub fn define_mutable(&mut self, name: String, type_: Type) -> Result<(), String> {
+        self.mutable.insert(name.clone());
+        self.values.insert(name, Scheme::mono(type_));
+        Ok(())
+    }
+
+    pub fn with_ref(context: Context) -> Self {
+        Self {
+            enclosing: Some(Box::new(context)),
+            ..Self::default()
+        }
+    }
+
+    pub fn lookup(&mut self, name: String) -> Option<Scheme> {
         if let Some(value) = self.values.get(&name).cloned() {
             return Some(value);
+        } else if let Some(enclosing) = &mut self.enclosing {
+            return enclosing.lookup(name);
+        }
+        None
+    }
+
+    pub fn define_class(&mut self, name: String, class: ClassType) {
+        self.classes.insert(name, class);
+    }
+
+    /// The closest class named `name`.
+    pub fn lookup_class(&self, name: &str) -> Option<&ClassType> {
+        match self.classes.get(name) {
+            Some(class) => Some(class),
+            None => self.enclosing.as_ref()?.lookup_class(name),
+        }
+    }
+
+    fn lookup_class_mut(&mut self, name: &str) -> Option<&mut ClassType> {
+        match self.classes.get_mut(name) {
+            Some(class) => Some(class),
+            None => self.enclosing.as_mut()?.lookup_class_mut(name),
+        }
+    }
+
+    /// Whether the closest binding named `name` was declared with `let mut`.
+    pub fn is_mutable(&self, name: &str) -> Option<bool> {
+        if self.values.contains_key(name) {
+            return Some(self.mutable.contains(name));
+        } else if let Some(enclosing) = &self.enclosing {
+            return enclosing.is_mutable(name);
         }
         None
     }
 }
 
-#[derive(Debug, Default)]
+/// What the typechecker knows about a declared class.
+#[derive(Debug, Default, Clone)]
+pub struct ClassType {
+    pub params: Vec<(String, Type)>,
+    pub fields: HashMap<String, Type>,
+    pub methods: HashMap<String, Type>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Typechecker {
     ctx: Context,
+    errors: Vec<Error>,
+    /// Types the type variables have been solved to so far.
+    subst: HashMap<usize, Type>,
+    next_var: usize,
+    /// Return types of the functions being checked, innermost last.
+    returns: Vec<Type>,
+    /// Labels of the loops enclosing the current statement in this function.
+    loops: Vec<Option<String>>,
+}
+
+impl Default for Typechecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Typechecker {
     pub fn new() -> Self {
-        Self {
+        let mut tc = Self {
             ctx: Context::default(),
+            errors: Vec::new(),
+            subst: HashMap::new(),
+            next_var: 0,
+            returns: Vec::new(),
+            loops: Vec::new(),
+        };
+
+        for builtin in crate::builtin::registry() {
+            tc.declare(builtin.name, builtin.signature);
         }
+
+        tc
     }
 
-    pub fn typecheck(&mut self, program: &Prog) -> Result<Vec<HirExpr>, Error> {
-        let mut value = Vec::new();
-        match program {
-            Prog::Body(stmts) => {
-                for stmt in stmts {
-                    value.push(self.stmt_eval(stmt)?);
+    /// A typechecker that also knows the native functions registered in
+    /// `env`.
+    pub fn with_environment(env: &Environment) -> Self {
+        let mut tc = Self::new();
+        let mut natives: Vec<_> = env
+            .vals
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::NativeFunction(native) => Some((name, native.signature.clone())),
+                _ => None,
+            })
+            .collect();
+        natives.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (name, signature) in natives {
+            tc.declare(name, signature);
+        }
+
+        tc
+    }
+
+    /// Makes a function defined outside of Martta known, with type
+    /// variables in `signature` generalised.
+    pub fn declare(&mut self, name: &str, signature: Type) {
+        let mut vars = Vec::new();
+        Self::free_vars(&signature, &mut vars);
+        let scheme = Scheme {
+            vars,
+            ty: signature,
+        };
+        // Fresh variables keep the signatures apart from inferred types.
+        let type_ = self.instantiate(&scheme);
+        let scheme = self.generalize(&type_);
+        self.ctx
+            .define_scheme(name.to_string(), scheme)
+            .unwrap_or_default();
+    }
+
+    /// Typechecks a whole program, returning every error found in it rather
+    /// than stopping at the first one.
+    pub fn typecheck(&mut self, program: &Prog) -> Result<Vec<HirExpr>, Vec<Error>> {
+        let value = match program {
+            Prog::Body(stmts) => self.eval_block(stmts),
+        };
+
+        match std::mem::take(&mut self.errors) {
+            errors if errors.is_empty() => Ok(value),
+            errors => Err(errors),
+        }
+    }
+
+    /// Typechecks every statement of a block. Statements that fail are
+    /// recorded and left out of the resulting HIR.
+    pub fn eval_block(&mut self, stmts: &[Stmt]) -> HirBlock {
+        let mut block = Vec::new();
+
+        for statement in stmts {
+            match self.stmt_eval(statement) {
+                Ok(hir) => block.push(hir),
+                Err(e) => self.errors.push(e),
+            }
+        }
+
+        block
+    }
+
+    pub fn block_type(block: &[HirExpr]) -> Type {
+        match block.last() {
+            Some(hir) => hir.ty(),
+            None => Type::Primitive(Primitive::Unit),
+        }
+    }
+
+    fn enter_scope(&mut self) {
+        let enclosing = std::mem::take(&mut self.ctx);
+        self.ctx = Context::with_ref(enclosing);
+    }
+
+    fn exit_scope(&mut self) {
+        if let Some(enclosing) = self.ctx.enclosing.take() {
+            self.ctx = *enclosing;
+        }
+    }
+
+    pub fn fresh(&mut self) -> Type {
+        self.next_var += 1;
+        Type::Var(self.next_var - 1)
+    }
+
+    /// Applies the current substitution to `ty`, all the way down.
+    pub fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(v) => match self.subst.get(v) {
+                Some(t) => self.resolve(t),
+                None => ty.clone(),
+            },
+            Type::Primitive(Primitive::List(t)) => {
+                Type::Primitive(Primitive::List(Box::new(self.resolve(t))))
+            }
+            Type::Function(params, ret) => Type::Function(
+                params.iter().map(|p| self.resolve(p)).collect(),
+                Box::new(self.resolve(ret)),
+            ),
+            _ => ty.clone(),
+        }
+    }
+
+    /// Collects the type variables of an already resolved type, in order.
+    fn free_vars(ty: &Type, vars: &mut Vec<usize>) {
+        match ty {
+            Type::Var(v) if !vars.contains(v) => vars.push(*v),
+            Type::Primitive(Primitive::List(t)) => Self::free_vars(t, vars),
+            Type::Function(params, ret) => {
+                for p in params {
+                    Self::free_vars(p, vars);
                 }
+                Self::free_vars(ret, vars);
             }
+            _ => {}
         }
-        Ok(value)
     }
 
-    pub fn eval_block(&mut self, stmts: Vec<Stmt>) -> Result<HirExpr, Error> {
-        let mut value: HirExpr = HirExpr::Nothing;
-        let steps = || -> Result<HirExpr, Error> {
-            for statement in stmts {
-                value = self.stmt_eval(&statement)?
+    /// Type variables that are still free somewhere in the context.
+    fn context_vars(&self) -> Vec<usize> {
+        let mut vars = Vec::new();
+        let mut ctx = Some(&self.ctx);
+
+        while let Some(scope) = ctx {
+            for scheme in scope.values.values() {
+                let mut free = Vec::new();
+                Self::free_vars(&self.resolve(&scheme.ty), &mut free);
+                vars.extend(free.into_iter().filter(|v| !scheme.vars.contains(v)));
             }
-            Ok(value)
-        };
-        let result = steps();
+            ctx = scope.enclosing.as_deref();
+        }
+
+        vars
+    }
+
+    /// Quantifies the variables of `ty` that the context doesn't mention.
+    pub fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.resolve(ty);
+        let context_vars = self.context_vars();
+        let mut vars = Vec::new();
+        Self::free_vars(&ty, &mut vars);
+        vars.retain(|v| !context_vars.contains(v));
+
+        Scheme { vars, ty }
+    }
+
+    /// Replaces the quantified variables of `scheme` with fresh ones.
+    pub fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let mapping: HashMap<usize, Type> =
+            scheme.vars.iter().map(|v| (*v, self.fresh())).collect();
+
+        fn replace(ty: &Type, mapping: &HashMap<usize, Type>) -> Type {
+            match ty {
+                Type::Var(v) => mapping.get(v).cloned().unwrap_or_else(|| ty.clone()),
+                Type::Primitive(Primitive::List(t)) => {
+                    Type::Primitive(Primitive::List(Box::new(replace(t, mapping))))
+                }
+                Type::Function(params, ret) => Type::Function(
+                    params.iter().map(|p| replace(p, mapping)).collect(),
+                    Box::new(replace(ret, mapping)),
+                ),
+                _ => ty.clone(),
+            }
+        }
+
+        replace(&scheme.ty, &mapping)
+    }
+
+    /// Looks `name` up and instantiates its type.
+    fn lookup(&mut self, name: &str) -> Option<Type> {
+        let scheme = self.ctx.lookup(name.to_string())?;
+        Some(self.instantiate(&scheme))
+    }
+
+    /// Typechecks a block in its own scope, as the interpreter runs it in a
+    /// child environment.
+    fn scoped_block(&mut self, stmts: &[Stmt]) -> HirBlock {
+        self.enter_scope();
+        let block = self.eval_block(stmts);
+        self.exit_scope();
+
+        block
+    }
+
+    /// Checks that a `break` or `continue` is inside a loop, and that the
+    /// loop it names, if any, encloses it.
+    fn check_loop_label(
+        &self,
+        keyword: &str,
+        label: &Option<String>,
+        span: Span,
+    ) -> Result<(), Error> {
+        match label {
+            _ if self.loops.is_empty() => Err(Error::TypeError(
+                format!("`{}` outside of a loop", keyword),
+                span,
+            )),
+            Some(l) if !self.loops.contains(label) => Err(Error::TypeError(
+                format!("use of undeclared label `{}`", l),
+                span,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Operand type of an arithmetic or comparison operator whose left hand
+    /// side has type `lhs`: `float` if it is one, and `int` otherwise.
+    fn number_type(&self, lhs: &Type) -> Type {
+        match self.resolve(lhs) {
+            Type::Primitive(Primitive::Float) => Type::Primitive(Primitive::Float),
+            _ => Type::Primitive(Primitive::Int),
+        }
+    }
+
+    /// Whether `expr` is a value that needs no evaluation beyond building
+    /// it, so its type can be generalised.
+    fn is_value(expr: &Expr) -> bool {
+        match expr {
+            Expr::Function(..)
+            | Expr::Var(..)
+            | Expr::Int(..)
+            | Expr::Float(..)
+            | Expr::Bool(..)
+            | Expr::Str(..) => true,
+            Expr::List(elements, _) => elements.iter().all(Self::is_value),
+            _ => false,
+        }
+    }
 
-        result
+    /// Type of the variable `name`, as long as it can be assigned to.
+    fn assignable(&mut self, name: &str, span: Span) -> Result<Type, Error> {
+        match (self.ctx.is_mutable(name), self.ctx.lookup(name.to_string())) {
+            (Some(true), Some(scheme)) => Ok(scheme.ty),
+            (Some(false), _) => Err(Error::TypeError(
+                format!("cannot assign twice to immutable variable `{}`", name),
+                span,
+            )),
+            _ => Err(Error::TypeError(
+                format!("cannot assign to undeclared variable `{}`", name),
+                span,
+            )),
+        }
+    }
+
+    /// Records a mismatch between `expected` and `found` without aborting.
+    fn expect(&mut self, expected: &Type, found: &Type, span: Span) {
+        if let Err(e) = self.unify(expected, found, span) {
+            self.errors.push(e);
+        }
     }
 
     pub fn ascription_type(&self, ascription: Ascription) -> Type {
         match ascription {
             Ascription::Int => Type::Primitive(Primitive::Int),
+            Ascription::Float => Type::Primitive(Primitive::Float),
             Ascription::Bool => Type::Primitive(Primitive::Bool),
             Ascription::Str => Type::Primitive(Primitive::Str),
-            Ascription::List(a) => match *a {
-                Ascription::Int => Type::Primitive(Primitive::List(Box::new(Primitive::Int))),
-                Ascription::Bool => Type::Primitive(Primitive::List(Box::new(Primitive::Bool))),
-                Ascription::Str => Type::Primitive(Primitive::List(Box::new(Primitive::Str))),
-                // Recursive primitive not allowed for now
-                _ => unreachable!(),
-            },
+            Ascription::Unit => Type::Primitive(Primitive::Unit),
+            Ascription::List(a) => {
+                Type::Primitive(Primitive::List(Box::new(self.ascription_type(*a))))
+            }
+            Ascription::Class(name) => Type::Class(name),
+            Ascription::Function(params, ret) => Type::Function(
+                params
+                    .into_iter()
+                    .map(|p| self.ascription_type(p))
+                    .collect(),
+                Box::new(self.ascription_type(*ret)),
+            ),
+        }
+    }
+
+    /// Unannotated parameters get fresh type variables to be inferred.
+    pub fn param_types(&mut self, params: &[Param]) -> Vec<Type> {
+        params
+            .iter()
+            .map(|p| match &p.ascription {
+                Some(a) => self.ascription_type(a.clone()),
+                None => self.fresh(),
+            })
+            .collect()
+    }
+
+    /// Parameter and return types of a function declaration.
+    fn signature(
+        &mut self,
+        params: &[Param],
+        ascription: &Option<Ascription>,
+    ) -> (Vec<Type>, Type) {
+        let params = self.param_types(params);
+        let return_type = match ascription {
+            Some(a) => self.ascription_type(a.clone()),
+            None => self.fresh(),
+        };
+
+        (params, return_type)
+    }
+
+    fn check_function(
+        &mut self,
+        name: &str,
+        args: &[Param],
+        stmts: &[Stmt],
+        (params, return_type): (Vec<Type>, Type),
+        span: Span,
+    ) -> HirExpr {
+        let body = self.function_body(args, &params, &return_type, stmts);
+        let body_span = stmts.last().map_or(span, |s| s.span());
+        self.expect(&return_type, &Self::block_type(&body), body_span);
+
+        HirExpr::Function(
+            name.to_owned(),
+            args.iter().map(|p| p.name.clone()).collect(),
+            body,
+            self.resolve(&Type::Function(params, Box::new(return_type))),
+            span,
+        )
+    }
+
+    /// Typechecks a function body in its own scope with its parameters bound.
+    /// Every `return` in it is checked against `return_type`.
+    fn function_body(
+        &mut self,
+        params: &[Param],
+        types: &[Type],
+        return_type: &Type,
+        stmts: &[Stmt],
+    ) -> HirBlock {
+        self.returns.push(return_type.clone());
+        // Loops outside the function can't be broken out of from inside it.
+        let loops = std::mem::take(&mut self.loops);
+        self.enter_scope();
+        for (param, type_) in params.iter().zip(types) {
+            self.ctx
+                .define(param.name.clone(), type_.clone())
+                .unwrap_or_default();
         }
+        let block = self.eval_block(stmts);
+        self.exit_scope();
+        self.loops = loops;
+        self.returns.pop();
+
+        block
+    }
+
+    /// Checks the arguments of a call against the callee's signature and
+    /// returns them along with the type of the call.
+    fn check_call(
+        &mut self,
+        callee: &str,
+        signature: &Type,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<(Vec<HirExpr>, Type), Error> {
+        let signature = match self.resolve(signature) {
+            Type::Var(v) => {
+                let params = args.iter().map(|_| self.fresh()).collect();
+                let function = Type::Function(params, Box::new(self.fresh()));
+                self.unify(&Type::Var(v), &function, span)?;
+                function
+            }
+            t => t,
+        };
+        let (params, ret) = match &signature {
+            Type::Function(params, ret) => (params, ret),
+            t => {
+                return Err(Error::TypeError(
+                    format!("`{}` is not a function, it has type `{}`", callee, t),
+                    span,
+                ))
+            }
+        };
+
+        if params.len() != args.len() {
+            return Err(Error::TypeError(
+                format!(
+                    "`{}` takes {} arguments but {} were supplied",
+                    callee,
+                    params.len(),
+                    args.len()
+                ),
+                span,
+            ));
+        }
+
+        let mut vals = Vec::new();
+        for (param, arg) in params.iter().zip(args) {
+            let hir = match (arg, self.resolve(param)) {
+                (Expr::Function(lambda, stmts, span), Type::Function(expected, _))
+                    if expected.len() == lambda.len() =>
+                {
+                    self.check_lambda(lambda, stmts, Some(&expected), *span)
+                }
+                _ => self.typecheck_expr(arg)?,
+            };
+            self.expect(param, &hir.ty(), arg.span());
+            vals.push(hir);
+        }
+
+        Ok((vals, (**ret).clone()))
+    }
+
+    /// Checks a lambda. Unannotated parameters take their types from
+    /// `expected` when the lambda is passed where a function is expected, so
+    /// `map(xs, |x| => { x + 1 })` knows `x` before checking the body.
+    fn check_lambda(
+        &mut self,
+        args: &[Param],
+        stmts: &[Stmt],
+        expected: Option<&[Type]>,
+        span: Span,
+    ) -> HirExpr {
+        let params = match expected {
+            Some(expected) => args
+                .iter()
+                .zip(expected)
+                .map(|(p, e)| match &p.ascription {
+                    Some(a) => self.ascription_type(a.clone()),
+                    None => e.clone(),
+                })
+                .collect(),
+            None => self.param_types(args),
+        };
+        let return_type = self.fresh();
+        let body = self.function_body(args, &params, &return_type, stmts);
+        let body_span = stmts.last().map_or(span, |s| s.span());
+        self.expect(&return_type, &Self::block_type(&body), body_span);
+        let type_ = self.resolve(&Type::Function(params, Box::new(return_type)));
+
+        HirExpr::Lambda(
+            args.iter().map(|p| p.name.clone()).collect(),
+            body,
+            type_,
+            span,
+        )
     }
 
     pub fn stmt_eval(&mut self, expr: &Stmt) -> Result<HirExpr, Error> {
         match expr {
             Stmt::Expr(x) => self.typecheck_expr(x),
-            Stmt::Func(name, args, stmts, ascription) => {
-                let return_type: Type = self.ascription_type(ascription.clone());
-                let body_type: Type = self.eval_block(stmts.to_vec())?.into();
-                let block_value = self.eval_block(stmts.to_vec())?;
+            Stmt::Func(name, args, stmts, ascription, span) => {
+                let (params, return_type) = self.signature(args, ascription);
+                let type_ = Type::Function(params.clone(), Box::new(return_type.clone()));
 
+                // Monomorphic inside its own body, generalised afterwards.
+                self.ctx
+                    .define(name.to_string(), type_.clone())
+                    .map_err(|e| Error::TypeError(e, *span))?;
+                let function = self.check_function(name, args, stmts, (params, return_type), *span);
+                self.ctx.values.remove(name);
+                let scheme = self.generalize(&type_);
                 self.ctx
-                    .define(name.to_string(), return_type.clone())
-                    .unwrap_or_else(|_| ());
-
-                assert_eq!(
-                    return_type, body_type,
-                    "Types mismatch. Expected {:?} as return type, but got {:?} instead.",
-                    return_type, body_type
-                );
-
-                Ok(HirExpr::Function(
-                    name.to_owned(),
-                    args.to_vec(),
-                    vec![block_value],
-                    return_type,
+                    .define_scheme(name.to_string(), scheme)
+                    .map_err(|e| Error::TypeError(e, *span))?;
+
+                Ok(function)
+            }
+            Stmt::Assign(name, rhs, annotation, mutability, span) => {
+                let expr_ = self.typecheck_expr(rhs)?;
+                let expected = match annotation {
+                    Some(a) => {
+                        let expected = self.ascription_type(a.clone());
+                        self.expect(&expected, &expr_.ty(), rhs.span());
+                        expected
+                    }
+                    None => expr_.ty(),
+                };
+
+                // The binding gets its declared type even when the right hand
+                // side is wrong, so later uses don't report the same mistake.
+                match mutability {
+                    // Only values are generalised: the result of a call may
+                    // hold on to state, such as a closure over a `let mut`,
+                    // that one instantiation writes and another reads.
+                    Mutability::Immutable if Self::is_value(rhs) => {
+                        let scheme = self.generalize(&expected);
+                        self.ctx.define_scheme(name.to_string(), scheme)
+                    }
+                    Mutability::Immutable => self.ctx.define(name.to_string(), expected.clone()),
+                    Mutability::Mutable => {
+                        self.ctx.define_mutable(name.to_string(), expected.clone())
+                    }
+                }
+                .map_err(|e| Error::TypeError(e, *span))?;
+
+                Ok(HirExpr::Assign(
+                    String::from(name),
+                    Box::new(expr_),
+                    self.resolve(&expected),
+                    *span,
                 ))
             }
-            Stmt::Assign(name, rhs, annotation) => {
-                let type_: Type = self.typecheck_expr(rhs)?.into();
+            Stmt::ReAssign(name, rhs, span) => {
+                let expected = self.assignable(name, *span)?;
                 let expr_ = self.typecheck_expr(rhs)?;
-                let expected = self.ascription_type(annotation.clone());
+                self.expect(&expected, &expr_.ty(), rhs.span());
 
-                self.ctx.define(name.to_string(), type_.clone())?;
+                Ok(HirExpr::ReAssign(
+                    String::from(name),
+                    Box::new(expr_),
+                    expected,
+                    *span,
+                ))
+            }
+            Stmt::IndexAssign(target, index, rhs, span) => {
+                let element = Expr::Index(Box::new(target.clone()), Box::new(index.clone()), *span);
+                let mut place = self.typecheck_expr(&element)?;
+                let expected = place.ty();
 
-                assert_eq!(
-                    type_, expected,
-                    "Types mismatch. Expected: {:?} but got {:?}",
-                    expected, type_
-                );
+                // Unwound from the innermost index out to the variable.
+                let mut indices = Vec::new();
+                let name = loop {
+                    match place {
+                        HirExpr::Index(list, index, ..) => {
+                            indices.push(*index);
+                            place = *list;
+                        }
+                        HirExpr::Var(name, ..) => break name,
+                        _ => {
+                            return Err(Error::TypeError(
+                                "invalid left-hand side of assignment".into(),
+                                target.span(),
+                            ))
+                        }
+                    }
+                };
+                indices.reverse();
+                self.assignable(&name, *span)?;
+                let expr_ = self.typecheck_expr(rhs)?;
+                self.expect(&expected, &expr_.ty(), rhs.span());
 
-                Ok(HirExpr::Assign(String::from(name), Box::new(expr_), type_))
+                Ok(HirExpr::IndexAssign(
+                    name,
+                    indices,
+                    Box::new(expr_),
+                    Type::Primitive(Primitive::Unit),
+                    *span,
+                ))
             }
-            Stmt::Return(e) => {
+            Stmt::Return(e, span) => {
+                let expected = match self.returns.last() {
+                    Some(t) => t.clone(),
+                    None => {
+                        return Err(Error::TypeError(
+                            "`return` outside of a function".into(),
+                            *span,
+                        ))
+                    }
+                };
                 let expr = self.typecheck_expr(e)?;
-                let type_: Type = self.typecheck_expr(e)?.into();
+                self.expect(&expected, &expr.ty(), e.span());
 
-                Ok(HirExpr::Return(Box::new(expr), type_))
+                // Control never continues past a return, so in a block's
+                // tail position it fits whatever type is expected there.
+                let type_ = self.fresh();
+                Ok(HirExpr::Return(Box::new(expr), type_, *span))
             }
-            Stmt::IfStatement(cond, stmts) => {
-                let type_: Type = self.typecheck_expr(cond)?.into();
-                let cond = self.typecheck_expr(cond)?;
-                let stmts = self.eval_block(stmts.clone())?;
-
-                assert_eq!(type_, Type::Primitive(Primitive::Bool));
+            Stmt::IfStatement(cond, stmts, span) => {
+                let cond_ = self.typecheck_expr(cond)?;
+                self.expect(&Type::Primitive(Primitive::Bool), &cond_.ty(), cond.span());
+                let stmts = self.scoped_block(stmts);
 
                 Ok(HirExpr::IfStatement(
-                    Box::new(cond.clone()),
-                    vec![stmts],
-                    type_,
+                    Box::new(cond_),
+                    stmts,
+                    Type::Primitive(Primitive::Unit),
+                    *span,
                 ))
             }
-            Stmt::IfElse(t1, t2, t3) => {
+            Stmt::IfElse(t1, t2, t3, span) => {
                 let h1 = self.typecheck_expr(t1)?;
-                let ty1: Type = self.typecheck_expr(t1)?.into();
+                self.expect(&Type::Primitive(Primitive::Bool), &h1.ty(), t1.span());
 
-                assert_eq!(ty1, Type::Primitive(Primitive::Bool));
+                let h2 = self.scoped_block(t2);
+                let h3 = self.scoped_block(t3);
+                let ty2 = Self::block_type(&h2);
+                let else_span = t3.last().map_or(*span, |s| s.span());
+                self.expect(&ty2, &Self::block_type(&h3), else_span);
 
-                let ty2: Type = self.eval_block(t2.to_vec())?.into();
-                let ty3: Type = self.eval_block(t3.to_vec())?.into();
-                let h2 = self.eval_block(t2.clone())?;
-                let h3 = self.eval_block(t3.clone())?;
+                Ok(HirExpr::IfElse(Box::new(h1), h2, h3, ty2, *span))
+            }
+            Stmt::While(cond, stmts, label, span) => {
+                let cond_ = self.typecheck_expr(cond)?;
+                self.expect(&Type::Primitive(Primitive::Bool), &cond_.ty(), cond.span());
+                self.loops.push(label.clone());
+                let body = self.scoped_block(stmts);
+                self.loops.pop();
+
+                Ok(HirExpr::While(
+                    Box::new(cond_),
+                    body,
+                    label.clone(),
+                    Type::Primitive(Primitive::Unit),
+                    *span,
+                ))
+            }
+            Stmt::For(var, iter, stmts, label, span) => {
+                let iter_ = self.typecheck_expr(iter)?;
+                match self.resolve(&iter_.ty()) {
+                    Type::Primitive(Primitive::List(_)) | Type::Var(_) => {}
+                    t => {
+                        return Err(Error::TypeError(
+                            format!("cannot iterate over a value of type `{}`", t),
+                            iter.span(),
+                        ))
+                    }
+                }
+                let element = self.fresh();
+                let list = Type::Primitive(Primitive::List(Box::new(element.clone())));
+                self.expect(&list, &iter_.ty(), iter.span());
 
-                assert_eq!(ty2, ty3, "Types must match: {:?} /= {:?}", ty2, ty3);
+                self.enter_scope();
+                let element = self.resolve(&element);
+                self.ctx
+                    .define(var.clone(), element)
+                    .map_err(|e| Error::TypeError(e, *span))?;
+                self.loops.push(label.clone());
+                let body = self.eval_block(stmts);
+                self.loops.pop();
+                self.exit_scope();
 
-                Ok(HirExpr::IfElse(Box::new(h1), vec![h2], vec![h3], ty2))
+                Ok(HirExpr::For(
+                    var.clone(),
+                    Box::new(iter_),
+                    body,
+                    label.clone(),
+                    Type::Primitive(Primitive::Unit),
+                    *span,
+                ))
+            }
+            Stmt::Break(label, span) => {
+                self.check_loop_label("break", label, *span)?;
+                let type_ = self.fresh();
+                Ok(HirExpr::Break(label.clone(), type_, *span))
+            }
+            Stmt::Continue(label, span) => {
+                self.check_loop_label("continue", label, *span)?;
+                let type_ = self.fresh();
+                Ok(HirExpr::Continue(label.clone(), type_, *span))
+            }
+            Stmt::Class(name, params, stmts, span) => {
+                let params: Vec<(String, Type)> = params
+                    .iter()
+                    .map(|(param, a)| (param.clone(), self.ascription_type(a.clone())))
+                    .collect();
+                let mut class = ClassType {
+                    params: params.clone(),
+                    ..ClassType::default()
+                };
+                let mut signatures = Vec::new();
+                for stmt in stmts {
+                    if let Stmt::Func(method, args, _, ascription, _) = stmt {
+                        let (params, return_type) = self.signature(args, ascription);
+                        let type_ = Type::Function(params.clone(), Box::new(return_type.clone()));
+                        class.methods.insert(method.clone(), type_);
+                        signatures.push((params, return_type));
+                    }
+                }
+                // Registered before the body so methods can use their own class.
+                self.ctx.define_class(name.clone(), class.clone());
+
+                // A class body runs when the class is instantiated, so a
+                // `return` in it can't belong to an enclosing function.
+                let returns = std::mem::take(&mut self.returns);
+                let loops = std::mem::take(&mut self.loops);
+                self.enter_scope();
+                for (param, type_) in &params {
+                    self.ctx
+                        .define(param.clone(), type_.clone())
+                        .map_err(|e| Error::TypeError(e, *span))?;
+                }
+                for (method, type_) in &class.methods {
+                    self.ctx
+                        .define(method.clone(), type_.clone())
+                        .map_err(|e| Error::TypeError(e, *span))?;
+                }
+                // Fields are checked first so every method can see all of them.
+                let (methods, fields): (Vec<&Stmt>, Vec<&Stmt>) =
+                    stmts.iter().partition(|s| matches!(s, Stmt::Func(..)));
+                let mut body = Vec::new();
+                for stmt in fields {
+                    match self.stmt_eval(stmt) {
+                        Ok(hir) => body.push(hir),
+                        Err(e) => self.errors.push(e),
+                    }
+                }
+                // Methods only define names in their own scopes, so the
+                // fields are known before they are checked, and a method can
+                // read them on other instances of its class.
+                let fields = self
+                    .ctx
+                    .values
+                    .iter()
+                    .filter(|(field, _)| !class.methods.contains_key(*field))
+                    .map(|(field, scheme)| (field.clone(), scheme.ty.clone()))
+                    .collect();
+                if let Some(class) = self.ctx.lookup_class_mut(name) {
+                    class.fields = fields;
+                }
+                for (stmt, signature) in methods.into_iter().zip(signatures) {
+                    if let Stmt::Func(method, args, stmts, _, span) = stmt {
+                        let hir = self.check_function(method, args, stmts, signature, *span);
+                        body.push(hir);
+                    }
+                }
+                self.exit_scope();
+                self.returns = returns;
+                self.loops = loops;
+
+                Ok(HirExpr::Class(
+                    name.clone(),
+                    params.into_iter().map(|(param, _)| param).collect(),
+                    body,
+                    Type::Class(name.clone()),
+                    *span,
+                ))
             }
-            _ => Err(Error::TypeError(
-                "The type system does not support other expressions yet".into(),
-            )),
         }
     }
 
+    fn typecheck_new(&mut self, name: &str, args: &[Expr], span: Span) -> Result<HirExpr, Error> {
+        let class = match self.ctx.lookup_class(name) {
+            Some(class) => class.clone(),
+            None => return Err(Error::TypeError(format!("unknown class `{}`", name), span)),
+        };
+
+        if class.params.len() != args.len() {
+            return Err(Error::TypeError(
+                format!(
+                    "class `{}` takes {} arguments but {} were supplied",
+                    name,
+                    class.params.len(),
+                    args.len()
+                ),
+                span,
+            ));
+        }
+
+        let mut vals = Vec::new();
+        for ((_, type_), arg) in class.params.iter().zip(args) {
+            let hir = self.typecheck_expr(arg)?;
+            self.expect(type_, &hir.ty(), arg.span());
+            vals.push(hir);
+        }
+
+        Ok(HirExpr::New(
+            name.to_string(),
+            vals,
+            Type::Class(name.to_string()),
+            span,
+        ))
+    }
+
     pub fn typecheck_expr(&mut self, expr: &Expr) -> Result<HirExpr, Error> {
         match expr {
-            Expr::Int(literal) => Ok(HirExpr::Literal(
+            Expr::Int(literal, span) => Ok(HirExpr::Literal(
                 Literal::Int(*literal),
                 Type::Primitive(Primitive::Int),
+                *span,
+            )),
+            Expr::Interpolation(parts, span) => {
+                let mut parts_ = Vec::new();
+                for part in parts {
+                    parts_.push(self.typecheck_expr(part)?);
+                }
+
+                Ok(HirExpr::Interpolation(
+                    parts_,
+                    Type::Primitive(Primitive::Str),
+                    *span,
+                ))
+            }
+            Expr::Float(literal, span) => Ok(HirExpr::Literal(
+                Literal::Float(*literal),
+                Type::Primitive(Primitive::Float),
+                *span,
             )),
-            Expr::Bool(literal) => Ok(HirExpr::Literal(
+            Expr::Bool(literal, span) => Ok(HirExpr::Literal(
                 Literal::Bool(*literal),
                 Type::Primitive(Primitive::Bool),
+                *span,
             )),
-            Expr::Var(v) => {
-                let type_ = match self.ctx.lookup(v.to_string()) {
+            Expr::Var(v, span) => {
+                let type_ = match self.lookup(v) {
                     Some(t) => t,
-                    None => Type::Primitive(Primitive::Int),
+                    None => {
+                        return Err(Error::TypeError(
+                            format!("cannot find value `{}` in this scope", v),
+                            *span,
+                        ))
+                    }
                 };
 
-                Ok(HirExpr::Var(v.to_string(), type_))
+                Ok(HirExpr::Var(v.to_string(), type_, *span))
             }
-            Expr::Str(s) => Ok(HirExpr::Literal(
+            Expr::Str(s, span) => Ok(HirExpr::Literal(
                 Literal::String(s.to_string()),
                 Type::Primitive(Primitive::Str),
+                *span,
             )),
-            Expr::Function(args, stmts) => {
-                let body = self.eval_block(stmts.clone())?;
-                let type_: Type = self.eval_block(stmts.to_vec())?.into();
-
-                Ok(HirExpr::Lambda(args.to_vec(), vec![body], type_))
-            }
-            Expr::List(elements) => {
-                let mut typechecked_expressions: Vec<Type> = Vec::new();
+            Expr::Function(args, stmts, span) => Ok(self.check_lambda(args, stmts, None, *span)),
+            Expr::List(elements, span) => {
                 let mut parsed_exprs = Vec::new();
 
                 for el in elements {
-                    typechecked_expressions.push(self.typecheck_expr(el)?.into());
                     parsed_exprs.push(self.typecheck_expr(el)?);
                 }
 
-                let type_ = || -> Type {
-                    let head = typechecked_expressions.first().unwrap();
-                    typechecked_expressions
-                        .iter()
-                        .all(|t| t == head)
-                        .then(|| head)
-                        .clone()
-                        .unwrap()
-                        .clone()
+                let type_ = match parsed_exprs.first() {
+                    Some(head) => head.ty(),
+                    None => self.fresh(),
                 };
 
-                Ok(HirExpr::List(parsed_exprs, type_()))
+                for el in parsed_exprs.iter().skip(1) {
+                    self.expect(&type_, &el.ty(), el.span());
+                }
+
+                Ok(HirExpr::List(parsed_exprs, type_, *span))
             }
-            Expr::Binary(lhs, op, rhs) => {
+            // `x += e` is checked, and run, as `x = x + e`.
+            Expr::Binary(lhs, Operator::SumTo, rhs, span) => match lhs.as_ref() {
+                Expr::Var(name, _) => {
+                    let sum = Expr::Binary(lhs.clone(), Operator::Add, rhs.clone(), *span);
+                    self.stmt_eval(&Stmt::ReAssign(name.clone(), sum, *span))
+                }
+                Expr::Index(list, index, _) => {
+                    let sum = Expr::Binary(lhs.clone(), Operator::Add, rhs.clone(), *span);
+                    let assign = Stmt::IndexAssign(*list.clone(), *index.clone(), sum, *span);
+                    self.stmt_eval(&assign)
+                }
+                _ => Err(Error::TypeError(
+                    "invalid left-hand side of `+=`".into(),
+                    lhs.span(),
+                )),
+            },
+            Expr::Binary(lhs, op, rhs, span) => {
                 let lhs_ = self.typecheck_expr(lhs)?;
                 let rhs_ = self.typecheck_expr(rhs)?;
+                let bool_ = Type::Primitive(Primitive::Bool);
 
                 let type_ = match op {
-                    Operator::Add | Operator::Sub | Operator::Div => {
-                        let ty = self.unify(&lhs_.clone().into(), &rhs_.clone().into())?;
-
-                        ty
+                    Operator::Add
+                        if self.resolve(&lhs_.ty()) == Type::Primitive(Primitive::Str) =>
+                    {
+                        let string = Type::Primitive(Primitive::Str);
+                        self.expect(&string, &rhs_.ty(), rhs.span());
+                        string
+                    }
+                    Operator::Add
+                    | Operator::Sub
+                    | Operator::Mul
+                    | Operator::Div
+                    | Operator::Mod => {
+                        let number = self.number_type(&lhs_.ty());
+                        self.expect(&number, &lhs_.ty(), lhs.span());
+                        self.expect(&number, &rhs_.ty(), rhs.span());
+                        number
+                    }
+                    Operator::LessThan
+                    | Operator::LessOrEqual
+                    | Operator::GreaterThan
+                    | Operator::GreaterOrEqual => {
+                        let number = self.number_type(&lhs_.ty());
+                        self.expect(&number, &lhs_.ty(), lhs.span());
+                        self.expect(&number, &rhs_.ty(), rhs.span());
+                        bool_
+                    }
+                    Operator::Or | Operator::And => {
+                        self.expect(&bool_, &lhs_.ty(), lhs.span());
+                        self.expect(&bool_, &rhs_.ty(), rhs.span());
+                        bool_
                     }
-                    Operator::EqTo
-                    | Operator::Or
-                    | Operator::And
-                    | Operator::LessThan
-                    | Operator::GreaterThan => Type::Primitive(Primitive::Bool),
-                    _ => unimplemented!("Unimplemented type operator"),
+                    Operator::EqTo | Operator::NotEq => {
+                        self.expect(&lhs_.ty(), &rhs_.ty(), rhs.span());
+                        bool_
+                    }
+                    Operator::SumTo => unreachable!("`+=` is desugared above"),
                 };
 
                 Ok(HirExpr::Binary(
-                    Box::new(lhs_.clone()),
+                    Box::new(lhs_),
                     op.clone(),
-                    Box::new(rhs_.clone()),
+                    Box::new(rhs_),
                     type_,
+                    *span,
                 ))
             }
-            Expr::Call(Call::Function(Function {
-                func: function,
-                args,
-            })) => {
-                let mut vals = Vec::new();
-                let type_: Type = match self.ctx.lookup(function.to_string()) {
-                    Some(t) => t,
-                    None => Type::Primitive(Primitive::Int),
+            Expr::Unary(op, operand, span) => {
+                let operand_ = self.typecheck_expr(operand)?;
+                let type_ = match op {
+                    UnaryOperator::Neg => self.number_type(&operand_.ty()),
+                    UnaryOperator::Not => Type::Primitive(Primitive::Bool),
                 };
+                self.expect(&type_, &operand_.ty(), operand.span());
 
-                for arg in args {
-                    match self.typecheck_expr(arg) {
-                        Ok(v) => vals.push(v),
-                        Err(e) => return Err(e),
+                Ok(HirExpr::Unary(op.clone(), Box::new(operand_), type_, *span))
+            }
+            Expr::Call(
+                Call::Function(Function {
+                    func: function,
+                    args,
+                }),
+                span,
+            ) if self.ctx.lookup_class(function).is_some() => {
+                self.typecheck_new(function, args, *span)
+            }
+            Expr::Call(
+                Call::Function(Function {
+                    func: function,
+                    args,
+                }),
+                span,
+            ) => {
+                let (vals, type_) = match self.lookup(function) {
+                    Some(signature) => self.check_call(function, &signature, args, *span)?,
+                    None => {
+                        return Err(Error::TypeError(
+                            format!("cannot find function `{}` in this scope", function),
+                            *span,
+                        ))
                     }
-                }
-                // TODO: Check if arguments number matches
+                };
+
                 Ok(HirExpr::Call(
                     HirFunction(function.to_string(), vals),
                     type_,
+                    *span,
+                ))
+            }
+            Expr::Call(Call::Class(Class { identifier, args }), span) => {
+                self.typecheck_new(identifier, args, *span)
+            }
+            Expr::Range(start, end, inclusive, span) => {
+                let int = Type::Primitive(Primitive::Int);
+                let start_ = self.typecheck_expr(start)?;
+                self.expect(&int, &start_.ty(), start.span());
+                let end_ = self.typecheck_expr(end)?;
+                self.expect(&int, &end_.ty(), end.span());
+
+                Ok(HirExpr::Range(
+                    Box::new(start_),
+                    Box::new(end_),
+                    *inclusive,
+                    Type::Primitive(Primitive::List(Box::new(int))),
+                    *span,
+                ))
+            }
+            Expr::Index(list, index, span) => {
+                let list_ = self.typecheck_expr(list)?;
+                match self.resolve(&list_.ty()) {
+                    Type::Primitive(Primitive::List(_)) | Type::Var(_) => {}
+                    t => {
+                        return Err(Error::TypeError(
+                            format!("cannot index into a value of type `{}`", t),
+                            list.span(),
+                        ))
+                    }
+                }
+                let element = self.fresh();
+                let list_type = Type::Primitive(Primitive::List(Box::new(element.clone())));
+                self.expect(&list_type, &list_.ty(), list.span());
+                let int = Type::Primitive(Primitive::Int);
+
+                // `xs[a..b]` is a slice rather than an index with a list.
+                if let Expr::Range(start, end, inclusive, _) = index.as_ref() {
+                    let start_ = self.typecheck_expr(start)?;
+                    self.expect(&int, &start_.ty(), start.span());
+                    let end_ = self.typecheck_expr(end)?;
+                    self.expect(&int, &end_.ty(), end.span());
+
+                    return Ok(HirExpr::Slice(
+                        Box::new(list_),
+                        Box::new(start_),
+                        Box::new(end_),
+                        *inclusive,
+                        self.resolve(&list_type),
+                        *span,
+                    ));
+                }
+
+                let index_ = self.typecheck_expr(index)?;
+                self.expect(&int, &index_.ty(), index.span());
+
+                Ok(HirExpr::Index(
+                    Box::new(list_),
+                    Box::new(index_),
+                    self.resolve(&element),
+                    *span,
+                ))
+            }
+            Expr::Field(obj, field, span) => {
+                let obj_ = self.typecheck_expr(obj)?;
+                let obj_type = self.resolve(&obj_.ty());
+                let type_ = match &obj_type {
+                    Type::Class(class) => self
+                        .ctx
+                        .lookup_class(class)
+                        .and_then(|class| class.fields.get(field).cloned()),
+                    _ => None,
+                };
+
+                match type_ {
+                    Some(type_) => Ok(HirExpr::Field(Box::new(obj_), field.clone(), type_, *span)),
+                    None => Err(Error::TypeError(
+                        format!("no field `{}` on type `{}`", field, obj_type),
+                        *span,
+                    )),
+                }
+            }
+            Expr::MethodCall(obj, Function { func: method, args }, span) => {
+                let obj_ = self.typecheck_expr(obj)?;
+                let obj_type = self.resolve(&obj_.ty());
+                let signature = match &obj_type {
+                    Type::Class(class) => self
+                        .ctx
+                        .lookup_class(class)
+                        .and_then(|class| class.methods.get(method).cloned()),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    Error::TypeError(
+                        format!("no method `{}` on type `{}`", method, obj_type),
+                        *span,
+                    )
+                })?;
+
+                let (vals, type_) = self.check_call(method, &signature, args, *span)?;
+
+                Ok(HirExpr::MethodCall(
+                    Box::new(obj_),
+                    HirFunction(method.clone(), vals),
+                    type_,
+                    *span,
                 ))
             }
-            _ => Err(Error::TypeError(
-                "The type system does not support this type yet".into(),
-            )),
         }
     }
 
-    pub fn unify(&self, ty1: &Type, ty2: &Type) -> Result<Type, Error> {
-        match (ty1, ty2) {
-            (Type::Primitive(p1), Type::Primitive(p2)) if p1 == p2 => Ok(ty2.clone()),
-            (Type::Primitive(p1), Type::Primitive(p2)) if p1 != p2 => {
-                Err(Error::TypeError("Types do not unify".into()))
+    /// Makes `ty1` and `ty2` equal by solving type variables, failing if
+    /// their shapes differ.
+    pub fn unify(&mut self, ty1: &Type, ty2: &Type, span: Span) -> Result<Type, Error> {
+        let (ty1, ty2) = (self.resolve(ty1), self.resolve(ty2));
+        let mismatch = |ty1: &Type, ty2: &Type| {
+            Error::TypeError(format!("expected `{}`, found `{}`", ty1, ty2), span)
+        };
+
+        match (&ty1, &ty2) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(ty1.clone()),
+            (Type::Var(v), t) | (t, Type::Var(v)) => {
+                let mut vars = Vec::new();
+                Self::free_vars(t, &mut vars);
+                if vars.contains(v) {
+                    return Err(Error::TypeError(
+                        format!(
+                            "cannot construct the infinite type `{}` = `{}`",
+                            Type::Var(*v),
+                            t
+                        ),
+                        span,
+                    ));
+                }
+                self.subst.insert(*v, t.clone());
+                Ok(t.clone())
+            }
+            (Type::Primitive(Primitive::List(a)), Type::Primitive(Primitive::List(b))) => {
+                self.unify(a, b, span).map_err(|_| mismatch(&ty1, &ty2))?;
+                Ok(self.resolve(&ty2))
+            }
+            (Type::Function(p1, r1), Type::Function(p2, r2)) if p1.len() == p2.len() => {
+                for (a, b) in p1.iter().zip(p2) {
+                    self.unify(a, b, span).map_err(|_| mismatch(&ty1, &ty2))?;
+                }
+                self.unify(r1, r2, span).map_err(|_| mismatch(&ty1, &ty2))?;
+                Ok(self.resolve(&ty2))
             }
-            (_, _) => unimplemented!(),
+            (a, b) if a == b => Ok(ty2.clone()),
+            (_, _) => Err(mismatch(&ty1, &ty2)),
         }
     }
 }